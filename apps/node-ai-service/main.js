@@ -30,20 +30,28 @@ const openai = new OpenAI({
 const agentBaseUrl = process.env.MASTRA_BASE_URL || "http://localhost:4111";
 console.log(`Connecting to agent service at: ${agentBaseUrl}`);
 
+/**
+ * Accept a request object, or the JSON string older callers pass
+ * @param {Object|string} request - The request
+ * @returns {Object} - The request object
+ */
+function parseRequest(request) {
+  return typeof request === 'string' ? JSON.parse(request) : request;
+}
 
 /**
  * Handle CopilotKit requests (non-streaming)
- * @param {string} requestJson - JSON string containing the request
+ * @param {Object|string} requestJson - The request, as an object or a JSON string
  * @returns {Promise<string>} - JSON string containing the response
  */
 async function handleCopilotRequest(requestJson) {
   try {
-    const request = JSON.parse(requestJson);
+    const request = parseRequest(requestJson);
     
     // If streaming is requested but we're in the non-streaming handler,
     // redirect to the streaming handler
     if (request.stream) {
-      return await handleCopilotStreamRequest(requestJson);
+      return await handleCopilotStreamRequest(request);
     }
     
     // Make a direct HTTP request to our agent service
@@ -81,12 +89,12 @@ async function handleCopilotRequest(requestJson) {
 
 /**
  * Handle streaming CopilotKit requests
- * @param {string} requestJson - JSON string containing the request
+ * @param {Object|string} requestJson - The request, as an object or a JSON string
  * @returns {Promise<string>} - JSON string containing the response chunks
  */
 async function handleCopilotStreamRequest(requestJson) {
   try {
-    const request = JSON.parse(requestJson);
+    const request = parseRequest(requestJson);
     const chunks = [];
     
     // Make a direct HTTP request to our agent service for streaming
@@ -164,12 +172,12 @@ async function handleCopilotStreamRequest(requestJson) {
 
 /**
  * Handle direct OpenAI streaming for more control
- * @param {string} requestJson - JSON string containing the request
+ * @param {Object|string} requestJson - The request, as an object or a JSON string
  * @returns {Promise<string>} - JSON string containing the response chunks
  */
 async function handleOpenAIStreamRequest(requestJson) {
   try {
-    const request = JSON.parse(requestJson);
+    const request = parseRequest(requestJson);
     const chunks = [];
     
     // Create a streaming completion
@@ -214,7 +222,7 @@ async function handleOpenAIStreamRequest(requestJson) {
 // Define API routes
 app.post('/api/copilot', async (req, res) => {
   try {
-    const response = await handleCopilotRequest(JSON.stringify(req.body));
+    const response = await handleCopilotRequest(req.body);
     res.json(JSON.parse(response));
   } catch (error) {
     console.error('Error in /api/copilot route:', error);
@@ -227,7 +235,7 @@ app.post('/api/copilot', async (req, res) => {
 
 app.post('/api/copilot/stream', async (req, res) => {
   try {
-    const response = await handleCopilotStreamRequest(JSON.stringify(req.body));
+    const response = await handleCopilotStreamRequest(req.body);
     res.json(JSON.parse(response));
   } catch (error) {
     console.error('Error in /api/copilot/stream route:', error);
@@ -240,7 +248,7 @@ app.post('/api/copilot/stream', async (req, res) => {
 
 app.post('/api/openai/stream', async (req, res) => {
   try {
-    const response = await handleOpenAIStreamRequest(JSON.stringify(req.body));
+    const response = await handleOpenAIStreamRequest(req.body);
     res.json(JSON.parse(response));
   } catch (error) {
     console.error('Error in /api/openai/stream route:', error);
@@ -0,0 +1,110 @@
+use std::future::{ready, Ready};
+use std::sync::{Arc, RwLock};
+
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::http::header;
+use actix_web::web::{self, Data};
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, Result as ActixResult};
+use tracing::warn;
+
+use crate::secrets::Secret;
+use crate::server::AppState;
+
+/// Bundle and storage management, which needs the Deno runtime
+#[cfg(feature = "deno")]
+mod deno;
+
+/// Bearer tokens accepted by the admin API
+#[derive(Clone, Default)]
+pub struct AdminKeys(Arc<RwLock<Vec<Secret>>>);
+
+impl AdminKeys {
+    /// Accept `keys`; no key at all disables the admin API
+    pub fn new(keys: Vec<Secret>) -> Self {
+        Self(Arc::new(RwLock::new(keys)))
+    }
+
+    /// Accept `keys` instead of the current ones, from the next request on
+    pub fn replace(&self, keys: Vec<Secret>) {
+        *self.0.write().unwrap() = keys;
+    }
+
+    /// Whether any key is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.0.read().unwrap().is_empty()
+    }
+
+    /// Whether `token` is one of the keys, compared in constant time
+    fn verify(&self, token: &str) -> bool {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .fold(false, |found, key| constant_time_eq(key.expose().as_bytes(), token.as_bytes()) | found)
+    }
+}
+
+/// A request authenticated with an admin key in its `Authorization: Bearer` header
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let authorized = match (req.app_data::<Data<AppState>>(), token) {
+            (Some(state), Some(token)) => state.admin_keys.verify(token),
+            _ => false,
+        };
+
+        if authorized {
+            return ready(Ok(Admin));
+        }
+
+        warn!(path = %req.path(), "Rejected unauthenticated admin request");
+        let response = HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .json(serde_json::json!({ "error": "Unauthorized" }));
+        ready(Err(InternalError::from_response("Unauthorized", response).into()))
+    }
+}
+
+/// Register the admin API under `/api/admin`
+///
+/// Bundle and storage management is only available with the `deno` feature.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/api/admin").route("/reload", web::post().to(reload_config));
+
+    #[cfg(feature = "deno")]
+    let scope = deno::routes(scope);
+
+    cfg.service(scope);
+}
+
+/// Re-read the configuration and apply the settings that can change while the server runs
+async fn reload_config(_admin: Admin, data: Data<AppState>) -> ActixResult<HttpResponse> {
+    let Some(reloader) = &data.reloader else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Configuration reload is not enabled",
+        })));
+    };
+
+    Ok(match reloader.reload().await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(errors) => HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Invalid configuration",
+            "errors": errors,
+        })),
+    })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
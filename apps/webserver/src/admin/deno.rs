@@ -0,0 +1,201 @@
+use actix_web::web::{self, Data};
+use actix_web::{HttpResponse, Result as ActixResult, Scope};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use super::Admin;
+use crate::bundles::BundleUpload;
+use crate::error::{Result, ServerError};
+use crate::request_id::RequestId;
+use crate::server::{json_body, AppState};
+use crate::storage::{AppStorage, StorageKind, StorageScope};
+
+/// Add the bundle and storage routes to the admin scope
+pub(super) fn routes(scope: Scope) -> Scope {
+    scope
+        .route("/bundles", web::get().to(list_bundles))
+        .route("/bundles/{name}", web::get().to(get_bundle))
+        .route("/bundles/{name}", web::post().to(upload_bundle))
+        .route("/bundles/{name}", web::delete().to(delete_bundle))
+        .route("/bundles/{name}/rollback", web::post().to(rollback_bundle))
+        .route("/bundles/{name}/versions/{version}", web::delete().to(delete_bundle_version))
+        .route("/storage", web::get().to(list_storage))
+        .route("/storage/{scope:.+}", web::get().to(get_storage))
+        .route("/storage/{scope:.+}", web::delete().to(clear_storage))
+}
+
+/// Body of a rollback request; without a version the previous one is restored
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RollbackRequest {
+    version: Option<u32>,
+}
+
+async fn list_bundles(_admin: Admin, data: Data<AppState>, request_id: RequestId) -> ActixResult<HttpResponse> {
+    Ok(match data.deno.bundles.list() {
+        Ok(bundles) => HttpResponse::Ok().json(serde_json::json!({ "bundles": bundles })),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn get_bundle(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    name: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+    Ok(match data.deno.bundles.info(&name) {
+        Ok(bundle) => HttpResponse::Ok().json(bundle),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn upload_bundle(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    name: web::Path<String>,
+    upload: web::Json<BundleUpload>,
+) -> ActixResult<HttpResponse> {
+    Ok(match data.deno.bundles.upload(&name, upload.into_inner()).await {
+        Ok(manifest) => HttpResponse::Created().json(manifest),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn rollback_bundle(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    name: web::Path<String>,
+    body: web::Bytes,
+) -> ActixResult<HttpResponse> {
+    let request: RollbackRequest = match json_body(&body) {
+        Ok(request) => request,
+        Err(response) => return Ok(response),
+    };
+
+    Ok(match data.deno.bundles.rollback(&name, request.version).await {
+        Ok(manifest) => HttpResponse::Ok().json(manifest),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn delete_bundle(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    name: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+    Ok(match data.deno.bundles.delete(&name).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn delete_bundle_version(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    path: web::Path<(String, u32)>,
+) -> ActixResult<HttpResponse> {
+    let (name, version) = path.into_inner();
+    Ok(match data.deno.bundles.delete_version(&name, version).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+/// Query of a storage clearing request; without a kind all storage is cleared
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClearStorageQuery {
+    kind: Option<StorageKind>,
+}
+
+async fn list_storage(_admin: Admin, data: Data<AppState>, request_id: RequestId) -> ActixResult<HttpResponse> {
+    let apps = AppStorage::list(&data.config.data_dir).and_then(|apps| {
+        apps.iter()
+            .map(|storage| Ok(serde_json::json!({ "app": storage.scope().to_string(), "usage": storage.usage()? })))
+            .collect::<Result<Vec<_>>>()
+    });
+
+    Ok(match apps {
+        Ok(apps) => HttpResponse::Ok().json(serde_json::json!({ "apps": apps })),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+async fn get_storage(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    scope: web::Path<String>,
+) -> ActixResult<HttpResponse> {
+    let Some(storage) = find_storage(&data, &scope) else {
+        return Ok(storage_not_found(&scope));
+    };
+
+    Ok(match storage.usage() {
+        Ok(usage) => HttpResponse::Ok().json(serde_json::json!({ "app": storage.scope().to_string(), "usage": usage })),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+/// Delete an application's storage, then reload its isolates so they reopen the files
+async fn clear_storage(
+    _admin: Admin,
+    data: Data<AppState>,
+    request_id: RequestId,
+    scope: web::Path<String>,
+    query: web::Query<ClearStorageQuery>,
+) -> ActixResult<HttpResponse> {
+    let Some(storage) = find_storage(&data, &scope) else {
+        return Ok(storage_not_found(&scope));
+    };
+
+    if let Err(err) = storage.clear(query.kind) {
+        return Ok(error_response(&request_id, err));
+    }
+    info!(request_id = %request_id, "Cleared {:?} storage of {}", query.kind, storage.scope());
+
+    let pool = match storage.scope() {
+        StorageScope::Main => data.deno.pool.clone(),
+        StorageScope::Mount(name) => data.deno.routes.current().get(name).map(|mount| mount.pool.clone()),
+        StorageScope::Bundle(name) => data.deno.bundles.get(name).map(|bundle| bundle.pool.clone()),
+    };
+    if let Some(pool) = pool {
+        if let Err(err) = pool.reload().await {
+            return Ok(error_response(&request_id, err));
+        }
+    }
+
+    Ok(match storage.usage() {
+        Ok(usage) => HttpResponse::Ok().json(serde_json::json!({ "app": storage.scope().to_string(), "usage": usage })),
+        Err(err) => error_response(&request_id, err),
+    })
+}
+
+/// Storage of the application named in an admin API path, if it has any
+fn find_storage(data: &AppState, scope: &str) -> Option<AppStorage> {
+    let storage = AppStorage::new(&data.config.data_dir, StorageScope::parse(scope)?);
+    storage.exists().then_some(storage)
+}
+
+fn storage_not_found(scope: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(serde_json::json!({ "error": format!("No storage for {}", scope) }))
+}
+
+/// Map an admin operation failure to a JSON error response
+fn error_response(request_id: &RequestId, err: ServerError) -> HttpResponse {
+    match err {
+        ServerError::BundleNotFound(_) => HttpResponse::NotFound().json(serde_json::json!({ "error": err.to_string() })),
+        ServerError::InvalidBundle(_) => {
+            HttpResponse::UnprocessableEntity().json(serde_json::json!({ "error": err.to_string() }))
+        }
+        err => {
+            error!(request_id = %request_id, "Admin request failed: {}", err);
+            HttpResponse::InternalServerError().json(serde_json::json!({ "error": err.to_string() }))
+        }
+    }
+}
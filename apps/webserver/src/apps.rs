@@ -0,0 +1,407 @@
+use std::sync::Arc;
+
+use actix_web::{guard, web, HttpRequest, HttpResponse, Responder, Result as ActixResult};
+use actix_web::web::Data;
+use actix_web_lab::sse::{self, Sse, ChannelStream};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{info, error, debug};
+
+use crate::backend::BackendStatus;
+use crate::bundles::{BundleLimits, Bundles};
+use crate::client_identity::ClientIdentity;
+use crate::config::ServerConfig;
+use crate::deno::DenoRuntime;
+use crate::error::Result;
+use crate::functions::{FunctionRegistry, SchemaViolation};
+use crate::ops::AppMetrics;
+use crate::pool::DenoPool;
+use crate::request_id::RequestId;
+use crate::routes::{RouteTable, Routes};
+use crate::server::{error_status, json_body, AppState};
+use crate::shutdown::SHUTDOWN_MESSAGE;
+use crate::watch;
+
+/// Deno applications served next to the AI backend: the application at `DENO_APP_PATH`,
+/// mounted modules and deployed function bundles
+#[derive(Clone)]
+pub struct DenoApps {
+    /// Runtime of the application at `DENO_APP_PATH`, the template for all others
+    pub(crate) runtime: Arc<DenoRuntime>,
+
+    /// Isolates of the application at `DENO_APP_PATH`, when it has been started
+    pub(crate) pool: Option<Arc<DenoPool>>,
+
+    /// Modules mounted from the route table
+    pub(crate) routes: Routes,
+
+    /// Function bundles deployed through the admin API
+    pub(crate) bundles: Arc<Bundles>,
+
+    watch: bool,
+}
+
+/// Response body for a value that fails schema validation
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorResponse {
+    error: String,
+    violations: Vec<SchemaViolation>,
+}
+
+impl DenoApps {
+    /// Start the mounted modules and the deployed bundles
+    pub async fn start(config: Arc<ServerConfig>) -> Result<Self> {
+        let runtime = Arc::new(DenoRuntime::new(config.clone()));
+
+        // Start a worker pool for each mounted module
+        let routes = Routes::new(RouteTable::start(&runtime, &config.mounts, config.watch).await?);
+
+        // Start the function bundles deployed through the admin API
+        let bundles = Arc::new(Bundles::start(
+            runtime.clone(),
+            config.bundles_dir.clone(),
+            config.data_dir.clone(),
+            BundleLimits::from_config(&config),
+        ).await?);
+
+        Ok(Self {
+            runtime,
+            pool: None,
+            routes,
+            bundles,
+            watch: config.watch,
+        })
+    }
+
+    /// Start the isolates of the application at `DENO_APP_PATH`
+    pub async fn start_main(&mut self, workers: usize) -> Result<Arc<DenoPool>> {
+        info!("Initializing Deno runtime with {} worker(s)...", workers);
+        let pool = DenoPool::new(self.runtime.clone(), workers).await?;
+
+        info!("Deno runtime initialized successfully");
+
+        // Reload the Deno application when its sources change
+        if self.watch {
+            watch::spawn(Arc::downgrade(&pool), self.runtime.module_graph().clone())?;
+        }
+
+        self.pool = Some(pool.clone());
+        Ok(pool)
+    }
+
+    /// Functions the application at `DENO_APP_PATH` registered for `/api/functions`
+    pub fn functions(&self) -> &FunctionRegistry {
+        self.runtime.functions()
+    }
+
+    /// Counters incremented through `Webserver.metrics`
+    pub fn metrics(&self) -> &AppMetrics {
+        &self.runtime.services().metrics
+    }
+
+    /// Health of the mounted modules, for `/api/status`
+    pub fn status(&self) -> BackendStatus {
+        let mounts = self.routes.current().stats();
+        let (ready, size) = mounts
+            .iter()
+            .fold((0, 0), |(ready, size), (_, stats)| (ready + stats.ready, size + stats.size));
+        let mounts: serde_json::Map<String, serde_json::Value> = mounts
+            .into_iter()
+            .map(|(name, stats)| (name, serde_json::json!(stats)))
+            .collect();
+
+        BackendStatus {
+            ready,
+            size,
+            details: serde_json::json!({
+                "mounts": mounts,
+                "functions": self.functions().names(),
+                "metrics": self.metrics().snapshot(),
+            }),
+        }
+    }
+}
+
+/// Register the function, bundle and mount routes
+///
+/// Mounts are matched against the current route table on each request, after every
+/// other API route.
+pub fn configure(cfg: &mut web::ServiceConfig, routes: &Routes) {
+    let routes = routes.clone();
+    cfg.service(
+        web::resource("/api/functions/{name}")
+            .route(web::post().to(handle_function))
+    )
+    .service(
+        web::resource("/api/bundles/{name}/{function}")
+            .route(web::post().to(handle_bundle_call))
+    )
+    .service(
+        web::resource("/{tail:.*}")
+            .guard(guard::fn_guard(move |ctx| {
+                routes.current().find(&ctx.head().method, ctx.head().uri.path()).is_some()
+            }))
+            .to(handle_mount)
+    );
+}
+
+/// Handler for requests to mounted Deno modules
+///
+/// The handler function receives `{ method, path, query, body }`, where `path` is
+/// relative to the mount and `body` is the parsed JSON body (or `null`).
+async fn handle_mount(
+    req: HttpRequest,
+    body: web::Bytes,
+    data: Data<AppState>,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
+) -> ActixResult<HttpResponse> {
+    // The table may have been replaced since the guard matched
+    let Some(mount) = data.deno.routes.current().find(req.method(), req.path()) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({ "error": "Not Found" })));
+    };
+
+    let body: serde_json::Value = match json_body(&body) {
+        Ok(body) => body,
+        Err(response) => return Ok(response),
+    };
+    let query = web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+
+    let request = serde_json::json!({
+        "method": req.method().as_str(),
+        "path": mount.subpath(req.path()),
+        "query": query,
+        "body": body,
+    });
+    let handler = mount.config.handler.clone();
+
+    if mount.config.stream {
+        if data.shutdown.is_draining() {
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({ "error": SHUTDOWN_MESSAGE })));
+        }
+
+        let (tx, rx) = sse::channel(100);
+        let shutdown = data.shutdown.clone();
+        actix_web::rt::spawn(async move {
+            let (chunk_tx, chunk_rx) = mpsc::channel::<serde_json::Value>(1);
+            // The receiver is moved in so the handler learns the client has gone away, and
+            // forwarding stops after a chunk marked `done`
+            let forward = async {
+                let mut chunk_rx = chunk_rx;
+                let mut done_sent = false;
+                while let Some(chunk) = chunk_rx.recv().await {
+                    if tx.send(sse::Data::new(chunk.to_string())).await.is_err() {
+                        break;
+                    }
+                    if chunk.get("done").and_then(serde_json::Value::as_bool).unwrap_or(false) {
+                        done_sent = true;
+                        break;
+                    }
+                }
+                done_sent
+            };
+            let call = async {
+                let worker = mount
+                    .pool
+                    .checkout()
+                    .await?
+                    .with_request_id(request_id.as_str())
+                    .with_client(client);
+                worker.stream(&handler, &request, chunk_tx).await
+            };
+
+            // Streams still open when the shutdown grace period ends are told why they stop
+            let outcome = tokio::select! {
+                outcome = async { futures::join!(call, forward) } => Some(outcome),
+                () = shutdown.expired() => None,
+            };
+            let chunk = match outcome {
+                Some((Ok(()), true)) => return,
+                Some((Ok(()), false)) => serde_json::json!({ "done": true }),
+                Some((Err(err), _)) => {
+                    error!(request_id = %request_id, "Mount {} failed: {}", mount.config.name, err);
+                    serde_json::json!({ "error": err.to_string(), "done": true })
+                }
+                None => serde_json::json!({ "error": SHUTDOWN_MESSAGE, "done": true }),
+            };
+            let _ = tx.send(sse::Data::new(chunk.to_string())).await;
+        });
+
+        let response = Sse::new(ChannelStream::new(rx))
+            .keep_alive(sse::KeepAlive::new().interval(std::time::Duration::from_secs(15)))
+            .respond_to(&req)
+            .map_into_boxed_body();
+        return Ok(response);
+    }
+
+    let result = match mount.pool.checkout().await {
+        Ok(worker) => {
+            worker
+                .with_request_id(request_id.as_str())
+                .with_client(client)
+                .call::<_, serde_json::Value>(&handler, &request)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(response) => Ok(HttpResponse::Ok().json(response)),
+        Err(err) => {
+            error!(request_id = %request_id, "Mount {} failed: {}", mount.config.name, err);
+            Ok(HttpResponse::build(error_status(&err)).json(serde_json::json!({ "error": err.to_string() })))
+        }
+    }
+}
+
+/// Handler for calls to functions registered by the Deno application
+///
+/// The JSON body is checked against the function's input schema before the call and
+/// the result against its output schema after it. Invalid input is rejected with 422.
+async fn handle_function(
+    path: web::Path<String>,
+    body: web::Bytes,
+    data: Data<AppState>,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
+) -> ActixResult<HttpResponse> {
+    let name = path.into_inner();
+    let (Some(pool), Some(spec)) = (&data.deno.pool, data.deno.functions().get(&name)) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Function {} is not registered", name),
+        })));
+    };
+
+    let input: serde_json::Value = match json_body(&body) {
+        Ok(input) => input,
+        Err(response) => return Ok(response),
+    };
+
+    if let Err(violations) = spec.validate_input(&input) {
+        debug!(request_id = %request_id, "Rejected input for {}: {:?}", name, violations);
+        return Ok(HttpResponse::UnprocessableEntity().json(ValidationErrorResponse {
+            error: format!("Input does not match the schema of {}", name),
+            violations,
+        }));
+    }
+
+    let result = match pool.checkout().await {
+        Ok(worker) => {
+            worker
+                .with_request_id(request_id.as_str())
+                .with_client(client)
+                .call::<_, serde_json::Value>(&name, &input)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    let output = match result {
+        Ok(output) => output,
+        Err(err) => {
+            error!(request_id = %request_id, "Function {} failed: {}", name, err);
+            return Ok(HttpResponse::build(error_status(&err)).json(serde_json::json!({ "error": err.to_string() })));
+        }
+    };
+
+    // A result that breaks the function's own contract is a server-side bug
+    if let Err(violations) = spec.validate_output(&output) {
+        error!(request_id = %request_id, "Function {} returned invalid output: {:?}", name, violations);
+        return Ok(HttpResponse::InternalServerError().json(ValidationErrorResponse {
+            error: format!("Output of {} does not match its schema", name),
+            violations,
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(output))
+}
+
+/// Handler for calls to functions exported by a deployed bundle
+///
+/// The JSON body is passed as the single argument to the function in the bundle's active version.
+/// Only exported functions can be called; globals such as `close` are not found.
+async fn handle_bundle_call(
+    path: web::Path<(String, String)>,
+    body: web::Bytes,
+    data: Data<AppState>,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
+) -> ActixResult<HttpResponse> {
+    let (name, function) = path.into_inner();
+    let Some(bundle) = data.deno.bundles.get(&name) else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Bundle {} is not deployed", name),
+        })));
+    };
+    if !bundle.exports.contains(&function) {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Bundle {} does not export a function {}", name, function),
+        })));
+    }
+
+    let input: serde_json::Value = match json_body(&body) {
+        Ok(input) => input,
+        Err(response) => return Ok(response),
+    };
+
+    let result = match bundle.pool.checkout().await {
+        Ok(worker) => {
+            worker
+                .with_request_id(request_id.as_str())
+                .with_client(client)
+                .call::<_, serde_json::Value>(&function, &input)
+                .await
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(output) => Ok(HttpResponse::Ok().json(output)),
+        Err(err) => {
+            error!(request_id = %request_id, "Bundle {} version {} failed: {}", name, bundle.manifest.version, err);
+            Ok(HttpResponse::build(error_status(&err)).json(serde_json::json!({ "error": err.to_string() })))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::server::test_state;
+
+    #[actix_web::test]
+    async fn bundle_calls_only_reach_exported_functions() {
+        let data = test_state(Arc::new(MockBackend::default()), "bundle-exports").await;
+        let upload = serde_json::from_value(serde_json::json!({
+            "files": { "main.ts": "export function greet({ name }) { return { hello: name }; }\nexport const version = 1;" },
+        }))
+        .unwrap();
+        data.deno.bundles.upload("hello", upload).await.unwrap();
+
+        let routes = data.deno.routes.clone();
+        let app = test::init_service(App::new().app_data(data.clone()).configure(|cfg| configure(cfg, &routes))).await;
+        let call = |function: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/api/bundles/hello/{}", function))
+                .set_json(serde_json::json!({ "name": "bundle" }))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, call("greet")).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body, serde_json::json!({ "hello": "bundle" }));
+
+        // Globals such as `close`, which would exit the process, and exports that are not functions
+        for function in ["close", "prompt", "version", "missing"] {
+            let response = test::call_service(&app, call(function)).await;
+            assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND, "{}", function);
+        }
+
+        let _ = std::fs::remove_dir_all(&data.config.data_dir);
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::sync::mpsc;
+
+use super::{AiBackend, BackendStatus, CopilotRequest, CopilotResponse};
+use crate::client_identity::ClientIdentity;
+use crate::error::Result;
+use crate::pool::DenoPool;
+
+/// Copilot answers from the embedded Deno application
+///
+/// Calls `handleCopilotRequest` and `handleCopilotStreamRequest` on a pooled isolate.
+pub struct DenoBackend {
+    pool: Arc<DenoPool>,
+}
+
+impl DenoBackend {
+    /// Serve requests from the isolates of `pool`
+    pub fn new(pool: Arc<DenoPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl AiBackend for DenoBackend {
+    fn name(&self) -> &'static str {
+        "deno"
+    }
+
+    fn copilot<'a>(
+        &'a self,
+        request_id: &'a str,
+        client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+    ) -> BoxFuture<'a, Result<CopilotResponse>> {
+        async move {
+            let worker = self.pool.checkout().await?.with_request_id(request_id).with_client(client.cloned());
+            worker.call("handleCopilotRequest", request).await
+        }
+        .boxed()
+    }
+
+    fn copilot_stream<'a>(
+        &'a self,
+        request_id: &'a str,
+        client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+        sink: mpsc::Sender<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let worker = self.pool.checkout().await?.with_request_id(request_id).with_client(client.cloned());
+            worker.stream("handleCopilotStreamRequest", request, sink).await
+        }
+        .boxed()
+    }
+
+    fn status(&self) -> BackendStatus {
+        let stats = self.pool.stats();
+        BackendStatus {
+            ready: stats.ready,
+            size: stats.size,
+            details: serde_json::json!(stats),
+        }
+    }
+}
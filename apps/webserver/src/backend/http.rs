@@ -0,0 +1,187 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{AiBackend, BackendStatus, CopilotRequest, CopilotResponse};
+use crate::client_identity::ClientIdentity;
+use crate::error::{Result, ServerError};
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// Time allowed to connect to the AI service
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time allowed for a complete non-streaming answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Copilot answers from the Node.js AI service in `apps/node-ai-service`
+///
+/// Requests are forwarded to its `/api/copilot` and `/api/copilot/stream` endpoints
+/// with the request ID in `X-Request-Id`. The streaming endpoint may answer with a
+/// JSON array of chunks or with server-sent events.
+pub struct HttpBackend {
+    client: reqwest::Client,
+    base_url: String,
+    /// Whether the last request reached the service; false until `probe` or a request succeeds
+    reachable: AtomicBool,
+}
+
+impl HttpBackend {
+    /// Forward requests to the AI service at `base_url`, such as `http://127.0.0.1:3001`
+    pub fn new(base_url: impl Into<String>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .map_err(|e| ServerError::Backend(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            reachable: AtomicBool::new(false),
+        })
+    }
+
+    /// Check once whether the service accepts connections, so `/api/status` is accurate
+    /// before the first copilot request
+    pub async fn probe(&self) -> bool {
+        let result = self.client.get(&self.base_url).timeout(CONNECT_TIMEOUT).send().await;
+        let reachable = !matches!(&result, Err(err) if err.is_connect() || err.is_timeout());
+        self.reachable.store(reachable, Ordering::Relaxed);
+        reachable
+    }
+
+    async fn post(
+        &self,
+        path: &str,
+        request_id: &str,
+        request: &CopilotRequest,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response> {
+        let mut builder = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .header(REQUEST_ID_HEADER.as_str(), request_id)
+            .json(request);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        let result = builder.send().await;
+        self.reachable.store(!matches!(&result, Err(err) if err.is_connect()), Ordering::Relaxed);
+        result.map_err(|e| upstream_error(path, e))
+    }
+}
+
+impl AiBackend for HttpBackend {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    // Client identities are not forwarded to the AI service
+    fn copilot<'a>(
+        &'a self,
+        request_id: &'a str,
+        _client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+    ) -> BoxFuture<'a, Result<CopilotResponse>> {
+        async move {
+            let path = "/api/copilot";
+            let response = self.post(path, request_id, request, Some(REQUEST_TIMEOUT)).await?;
+            let status = response.status();
+            let body = response.bytes().await.map_err(|e| upstream_error(path, e))?;
+
+            // The service reports failures as a response with an `error`, even with a 500
+            match serde_json::from_slice::<CopilotResponse>(&body) {
+                Ok(response) if status.is_success() => Ok(response),
+                Ok(response) => Err(ServerError::Backend(format!(
+                    "AI service responded with {}: {}",
+                    status,
+                    response.error.unwrap_or(response.message)
+                ))),
+                Err(err) if status.is_success() => {
+                    Err(ServerError::Backend(format!("Invalid response from AI service: {}", err)))
+                }
+                Err(_) => Err(ServerError::Backend(format!("AI service responded with {}", status))),
+            }
+        }
+        .boxed()
+    }
+
+    fn copilot_stream<'a>(
+        &'a self,
+        request_id: &'a str,
+        _client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+        sink: mpsc::Sender<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let path = "/api/copilot/stream";
+            let response = self.post(path, request_id, request, None).await?;
+            if !response.status().is_success() {
+                return Err(ServerError::Backend(format!("AI service responded with {}", response.status())));
+            }
+
+            let is_event_stream = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("text/event-stream"));
+
+            if !is_event_stream {
+                let body = response.bytes().await.map_err(|e| upstream_error(path, e))?;
+                let chunks: Vec<serde_json::Value> = serde_json::from_slice(&body)
+                    .map_err(|e| ServerError::Backend(format!("Invalid stream from AI service: {}", e)))?;
+                for chunk in chunks {
+                    if sink.send(chunk).await.is_err() {
+                        break;
+                    }
+                }
+                return Ok(());
+            }
+
+            // Forward each `data:` event as soon as it is complete
+            let mut body = response.bytes_stream();
+            let mut buffer = String::new();
+            while let Some(bytes) = body.next().await {
+                let bytes = bytes.map_err(|e| upstream_error(path, e))?;
+                buffer.push_str(&String::from_utf8_lossy(&bytes).replace("\r\n", "\n"));
+
+                while let Some(end) = buffer.find("\n\n") {
+                    let event: String = buffer.drain(..end + 2).collect();
+                    for data in event.lines().filter_map(|line| line.strip_prefix("data:")) {
+                        match serde_json::from_str(data.trim()) {
+                            Ok(chunk) => {
+                                if sink.send(chunk).await.is_err() {
+                                    return Ok(());
+                                }
+                            }
+                            Err(err) => warn!(request_id = %request_id, "Skipping invalid event from AI service: {}", err),
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn status(&self) -> BackendStatus {
+        let reachable = self.reachable.load(Ordering::Relaxed);
+        BackendStatus {
+            ready: usize::from(reachable),
+            size: 1,
+            details: serde_json::json!({ "url": self.base_url, "reachable": reachable }),
+        }
+    }
+}
+
+fn upstream_error(path: &str, err: reqwest::Error) -> ServerError {
+    if err.is_timeout() {
+        ServerError::Backend(format!("AI service timed out on {}", path))
+    } else {
+        ServerError::Backend(format!("Request to AI service {} failed: {}", path, err))
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Mutex;
+
+use futures::future::{BoxFuture, FutureExt};
+use tokio::sync::mpsc;
+
+use super::{AiBackend, BackendStatus, CopilotRequest, CopilotResponse, CopilotStreamChunk};
+use crate::client_identity::ClientIdentity;
+use crate::error::Result;
+
+/// In-process backend with canned answers, for tests and frontend development
+///
+/// Without a fixed reply it echoes the request's message. Streams send the reply
+/// one word per chunk, then a final `done` chunk. Every request is recorded.
+#[derive(Default)]
+pub struct MockBackend {
+    reply: Option<String>,
+    requests: Mutex<Vec<CopilotRequest>>,
+}
+
+impl MockBackend {
+    /// Answer every request with `reply`
+    pub fn with_reply(reply: impl Into<String>) -> Self {
+        Self {
+            reply: Some(reply.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<CopilotRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn answer(&self, request: &CopilotRequest) -> String {
+        self.requests.lock().unwrap().push(request.clone());
+        self.reply
+            .clone()
+            .unwrap_or_else(|| format!("Mock response to: {}", request.message))
+    }
+}
+
+impl AiBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn copilot<'a>(
+        &'a self,
+        _request_id: &'a str,
+        _client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+    ) -> BoxFuture<'a, Result<CopilotResponse>> {
+        let response = CopilotResponse {
+            message: self.answer(request),
+            actions: vec![],
+            error: None,
+        };
+        futures::future::ready(Ok(response)).boxed()
+    }
+
+    fn copilot_stream<'a>(
+        &'a self,
+        _request_id: &'a str,
+        _client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+        sink: mpsc::Sender<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<()>> {
+        let answer = self.answer(request);
+        async move {
+            let words = answer.split_inclusive(' ').map(|word| CopilotStreamChunk {
+                chunk: word.to_string(),
+                done: Some(false),
+                error: None,
+            });
+            let done = CopilotStreamChunk {
+                chunk: String::new(),
+                done: Some(true),
+                error: None,
+            };
+
+            for chunk in words.chain(std::iter::once(done)) {
+                if sink.send(serde_json::to_value(chunk)?).await.is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn status(&self) -> BackendStatus {
+        BackendStatus {
+            ready: 1,
+            size: 1,
+            details: serde_json::json!({ "requests": self.requests.lock().unwrap().len() }),
+        }
+    }
+}
+
+// `AppState` needs the Deno applications when the feature is on
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::server::{self, test_state};
+
+    #[actix_web::test]
+    async fn copilotkit_answers_with_the_backend_reply() {
+        let backend = Arc::new(MockBackend::with_reply("Hello there"));
+        let app = test::init_service(App::new().app_data(test_state(backend.clone(), "mock-reply").await).configure(server::configure_api)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/copilotkit")
+            .set_json(serde_json::json!({ "message": "Hi" }))
+            .to_request();
+        let response: CopilotResponse = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(response.message, "Hello there");
+        assert_eq!(response.error, None);
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].message, "Hi");
+    }
+
+    #[actix_web::test]
+    async fn copilotkit_stream_sends_words_then_done() {
+        let backend = Arc::new(MockBackend::with_reply("one two three"));
+        let app = test::init_service(App::new().app_data(test_state(backend, "mock-stream").await).configure(server::configure_api)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/copilotkit/stream")
+            .set_json(serde_json::json!({ "message": "Count" }))
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert!(response.status().is_success());
+        let body = test::read_body(response).await;
+
+        let chunks: Vec<CopilotStreamChunk> = String::from_utf8_lossy(&body)
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| serde_json::from_str(data.trim()).expect("chunk is JSON"))
+            .collect();
+        let text: String = chunks.iter().map(|chunk| chunk.chunk.as_str()).collect();
+        assert_eq!(text, "one two three");
+
+        let (last, words) = chunks.split_last().expect("stream has chunks");
+        assert_eq!(last.done, Some(true));
+        assert_eq!(last.error, None);
+        assert!(words.iter().all(|chunk| chunk.done == Some(false)));
+    }
+
+    #[actix_web::test]
+    async fn status_reports_the_mock_backend() {
+        let backend = Arc::new(MockBackend::default());
+        let app = test::init_service(App::new().app_data(test_state(backend, "mock-status").await).configure(server::configure_api)).await;
+
+        let req = test::TestRequest::get().uri("/api/status").to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["backend"], "mock");
+        assert_eq!(body["mock"]["requests"], 0);
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(feature = "deno")]
+pub mod deno;
+pub mod http;
+pub mod mock;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::client_identity::ClientIdentity;
+use crate::error::Result;
+
+#[cfg(feature = "deno")]
+pub use self::deno::DenoBackend;
+pub use self::http::HttpBackend;
+pub use self::mock::MockBackend;
+
+/// Request payload for the copilotkit endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopilotRequest {
+    pub message: String,
+    #[serde(default)]
+    pub context: serde_json::Value,
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// Response payload for the copilotkit endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopilotResponse {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Streaming chunk for the copilotkit endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopilotStreamChunk {
+    #[serde(default)]
+    pub chunk: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Health of a backend, reported by `/api/status`
+#[derive(Debug)]
+pub struct BackendStatus {
+    /// Units (isolates, upstream services) able to serve requests
+    pub ready: usize,
+
+    /// Units the backend is made of
+    pub size: usize,
+
+    /// Backend-specific details, reported under the backend's name
+    pub details: serde_json::Value,
+}
+
+/// Source of copilot answers
+///
+/// The copilotkit handlers only talk to this trait, so the server runs with the embedded
+/// Deno runtime, the Node.js AI service or an in-process mock, chosen in configuration.
+pub trait AiBackend: Send + Sync {
+    /// Short name used in logs and `/api/status`
+    fn name(&self) -> &'static str;
+
+    /// Answer a copilot request
+    ///
+    /// `client` is the caller's verified client certificate identity, when it presented one.
+    fn copilot<'a>(
+        &'a self,
+        request_id: &'a str,
+        client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+    ) -> BoxFuture<'a, Result<CopilotResponse>>;
+
+    /// Answer a copilot request chunk by chunk
+    ///
+    /// Each chunk, shaped like `CopilotStreamChunk`, is sent to `sink` as soon as it is
+    /// produced. Backends stop early once `sink` is closed.
+    fn copilot_stream<'a>(
+        &'a self,
+        request_id: &'a str,
+        client: Option<&'a ClientIdentity>,
+        request: &'a CopilotRequest,
+        sink: mpsc::Sender<serde_json::Value>,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Current health of the backend
+    fn status(&self) -> BackendStatus;
+}
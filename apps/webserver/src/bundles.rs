@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::config::{permission_list, DenoPermissions, ServerConfig, MIN_HEAP_MB};
+use crate::deno::DenoRuntime;
+use crate::error::{Result, ServerError};
+use crate::functions::ExportedFunctions;
+use crate::pool::{DenoPool, PoolStats};
+use crate::storage::{AppStorage, StorageScope};
+
+/// Name of the manifest written next to each version's files
+const MANIFEST_FILE: &str = "bundle.json";
+
+/// File holding the active version of a bundle
+const ACTIVE_FILE: &str = "active";
+
+/// File holding the highest version number ever assigned to a bundle
+const LATEST_FILE: &str = "latest";
+
+/// Largest number of isolates a single bundle may ask for
+const MAX_BUNDLE_WORKERS: usize = 8;
+
+/// Call timeout of bundles that do not set one, in seconds
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Heap limit of bundles that do not set one, in megabytes
+const DEFAULT_MAX_HEAP_MB: usize = 128;
+
+/// File extensions accepted in a bundle
+const BUNDLE_EXTENSIONS: &[&str] = &["ts", "tsx", "mts", "js", "mjs", "json"];
+
+/// Environment variables holding the server's own credentials, which no bundle may read
+const CREDENTIAL_VARS: &[&str] = &["ADMIN_API_KEYS", "WEBSERVER_AUTH_ADMIN_KEYS", "SECRETS_KEY"];
+
+/// Permissions granted to a bundle
+///
+/// Lists follow the same rules as `[deno.permissions]`: a missing or empty list
+/// denies the permission and `["*"]` allows everything. `allow_env` may only name
+/// variables that hold no secret, and never `*`. Bundles can never spawn programs
+/// or load native libraries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundlePermissions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_read: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_write: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_env: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_net: Option<Vec<String>>,
+}
+
+impl BundlePermissions {
+    /// Deno permissions of the bundle, refusing environment access to any of `secret_names`
+    ///
+    /// With the `env` secrets provider, secrets are environment variables, so a bundle
+    /// allowed to read them would get the secrets `DenoRuntime::for_bundle` withholds.
+    fn to_deno(&self, secret_names: &[&str]) -> Result<DenoPermissions> {
+        for name in self.allow_env.iter().flatten() {
+            if name == "*" {
+                return Err(ServerError::InvalidBundle(
+                    "allow_env cannot be \"*\", which would expose the server's secrets; list the variables instead".to_string(),
+                ));
+            }
+            if secret_names.contains(&name.as_str()) || CREDENTIAL_VARS.contains(&name.as_str()) {
+                return Err(ServerError::InvalidBundle(format!("allow_env cannot include {}, which holds a secret", name)));
+            }
+        }
+
+        let paths = |list: Option<Vec<String>>| list.map(|list| list.into_iter().map(PathBuf::from).collect());
+        Ok(DenoPermissions {
+            allow_read: paths(permission_list(&self.allow_read)),
+            allow_write: paths(permission_list(&self.allow_write)),
+            allow_env: permission_list(&self.allow_env),
+            allow_net: permission_list(&self.allow_net),
+            allow_run: None,
+            allow_ffi: None,
+        })
+    }
+}
+
+/// Permissions and resource limits of a bundle, chosen when it is uploaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleSettings {
+    /// Permissions granted to the bundle's isolates
+    #[serde(default)]
+    pub permissions: BundlePermissions,
+
+    /// Number of isolates serving the bundle
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+
+    /// Maximum seconds a call may run
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// V8 heap limit of each isolate, in megabytes
+    #[serde(default = "default_max_heap_mb")]
+    pub max_heap_mb: usize,
+
+    /// Enable `Deno.openKv()`, and with it Deno's other unstable APIs, if the server runs with `--deno-kv`
+    #[serde(default)]
+    pub kv: bool,
+}
+
+fn default_workers() -> usize {
+    1
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+fn default_max_heap_mb() -> usize {
+    DEFAULT_MAX_HEAP_MB
+}
+
+/// Body of a bundle upload
+#[derive(Debug, Deserialize)]
+pub struct BundleUpload {
+    /// Source files by path relative to the bundle root
+    pub files: BTreeMap<String, String>,
+
+    /// Entry module (default: `main.ts`)
+    #[serde(default = "default_main")]
+    pub main: String,
+
+    #[serde(flatten)]
+    pub settings: BundleSettings,
+}
+
+fn default_main() -> String {
+    "main.ts".to_string()
+}
+
+/// Description of one stored version of a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub name: String,
+    pub version: u32,
+    pub main: String,
+    pub files: Vec<String>,
+    /// Upload time, in seconds since the Unix epoch
+    pub created_at: u64,
+    pub settings: BundleSettings,
+}
+
+/// A bundle as reported by the admin API
+#[derive(Debug, Serialize)]
+pub struct BundleInfo {
+    pub name: String,
+    pub active: Option<u32>,
+    pub versions: Vec<BundleManifest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolStats>,
+}
+
+/// Largest resources an upload may ask for, set by the server configuration
+#[derive(Debug, Clone, Copy)]
+pub struct BundleLimits {
+    pub max_timeout: Duration,
+    pub max_heap_mb: usize,
+}
+
+impl BundleLimits {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            max_timeout: config.bundle_max_timeout,
+            max_heap_mb: config.bundle_max_heap_mb,
+        }
+    }
+}
+
+/// The active version of a bundle and the isolates serving it
+pub struct ActiveBundle {
+    pub manifest: BundleManifest,
+    pub pool: Arc<DenoPool>,
+    /// Functions the entry module exports, the only ones callers may reach
+    pub exports: ExportedFunctions,
+}
+
+/// Function bundles deployed at runtime through the admin API
+///
+/// Each version is stored in `<dir>/<name>/<version>/` with its manifest, and the
+/// active version is recorded in `<dir>/<name>/active` and the highest version ever
+/// assigned in `<dir>/<name>/latest`. Every active bundle runs in its own pool built
+/// with `DenoRuntime::for_bundle`.
+pub struct Bundles {
+    dir: PathBuf,
+    data_dir: PathBuf,
+    runtime: Arc<DenoRuntime>,
+    limits: BundleLimits,
+    active: RwLock<HashMap<String, Arc<ActiveBundle>>>,
+    /// Serializes uploads, rollbacks and deletions
+    changes: tokio::sync::Mutex<()>,
+}
+
+impl Bundles {
+    /// Start the active version of every stored bundle
+    ///
+    /// Bundles that fail to start are logged and left inactive until the next change.
+    pub async fn start(runtime: Arc<DenoRuntime>, dir: PathBuf, data_dir: PathBuf, limits: BundleLimits) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .map_err(|e| ServerError::Config(format!("Failed to create bundles directory {:?}: {}", dir, e)))?;
+
+        let bundles = Self {
+            dir,
+            data_dir,
+            runtime,
+            limits,
+            active: RwLock::new(HashMap::new()),
+            changes: tokio::sync::Mutex::new(()),
+        };
+
+        for name in bundles.names()? {
+            let Some(version) = bundles.active_version(&name)? else {
+                continue;
+            };
+            match bundles.activate(&name, version).await {
+                Ok(()) => info!("Started bundle {} version {}", name, version),
+                Err(err) => error!("Failed to start bundle {} version {}: {}", name, version, err),
+            }
+        }
+
+        Ok(bundles)
+    }
+
+    /// The active version of a bundle, for calls
+    pub fn get(&self, name: &str) -> Option<Arc<ActiveBundle>> {
+        self.active.read().unwrap().get(name).cloned()
+    }
+
+    /// Every stored bundle with its versions
+    pub fn list(&self) -> Result<Vec<BundleInfo>> {
+        let mut bundles = Vec::new();
+        for name in self.names()? {
+            match self.info(&name) {
+                Ok(info) => bundles.push(info),
+                Err(ServerError::BundleNotFound(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(bundles)
+    }
+
+    /// A stored bundle with its versions
+    pub fn info(&self, name: &str) -> Result<BundleInfo> {
+        validate_name(name)?;
+        let versions = self.versions(name)?;
+        if versions.is_empty() {
+            return Err(ServerError::BundleNotFound(name.to_string()));
+        }
+
+        let versions = versions
+            .into_iter()
+            .map(|version| self.manifest(name, version))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(BundleInfo {
+            name: name.to_string(),
+            active: self.get(name).map(|bundle| bundle.manifest.version),
+            pool: self.get(name).map(|bundle| bundle.pool.stats()),
+            versions,
+        })
+    }
+
+    /// Store a new version of a bundle and make it active
+    ///
+    /// The version is only kept if at least one isolate starts with it.
+    pub async fn upload(&self, name: &str, upload: BundleUpload) -> Result<BundleManifest> {
+        validate_name(name)?;
+        validate_upload(&upload, &self.limits)?;
+        upload.settings.permissions.to_deno(&self.runtime.secret_names())?;
+        let _changes = self.changes.lock().await;
+
+        // Numbers of deleted versions are never handed out again
+        let stored = self.versions(name)?.last().copied().unwrap_or(0);
+        let version = self.latest_version(name)?.max(stored) + 1;
+        fs::create_dir_all(self.bundle_dir(name))?;
+        fs::write(self.bundle_dir(name).join(LATEST_FILE), version.to_string())?;
+        let manifest = BundleManifest {
+            name: name.to_string(),
+            version,
+            main: upload.main,
+            files: upload.files.keys().cloned().collect(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            settings: upload.settings,
+        };
+
+        // Write into a staging directory and rename it, so a version is never half written
+        let staging = self.bundle_dir(name).join(format!(".upload-{}", uuid::Uuid::new_v4()));
+        let written = write_version(&staging, &upload.files, &manifest)
+            .and_then(|()| fs::rename(&staging, self.version_dir(name, version)).map_err(ServerError::from));
+        if let Err(err) = written {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err);
+        }
+
+        if let Err(err) = self.activate(name, version).await {
+            let _ = fs::remove_dir_all(self.version_dir(name, version));
+            return Err(err);
+        }
+
+        info!("Deployed bundle {} version {}", name, version);
+        Ok(manifest)
+    }
+
+    /// Make an earlier version active again, by default the one before the active version
+    pub async fn rollback(&self, name: &str, version: Option<u32>) -> Result<BundleManifest> {
+        validate_name(name)?;
+        let _changes = self.changes.lock().await;
+
+        let versions = self.versions(name)?;
+        if versions.is_empty() {
+            return Err(ServerError::BundleNotFound(name.to_string()));
+        }
+
+        let target = match version {
+            Some(version) if versions.contains(&version) => version,
+            Some(version) => return Err(ServerError::BundleNotFound(format!("{} version {}", name, version))),
+            None => {
+                let active = self.active_version(name)?.unwrap_or(u32::MAX);
+                versions
+                    .iter()
+                    .rev()
+                    .copied()
+                    .find(|&version| version < active)
+                    .ok_or_else(|| ServerError::InvalidBundle(format!("{} has no version before {}", name, active)))?
+            }
+        };
+
+        self.activate(name, target).await?;
+        info!("Rolled back bundle {} to version {}", name, target);
+        self.manifest(name, target)
+    }
+
+    /// Delete one inactive version of a bundle
+    pub async fn delete_version(&self, name: &str, version: u32) -> Result<()> {
+        validate_name(name)?;
+        let _changes = self.changes.lock().await;
+
+        if !self.versions(name)?.contains(&version) {
+            return Err(ServerError::BundleNotFound(format!("{} version {}", name, version)));
+        }
+        if self.active_version(name)? == Some(version) {
+            return Err(ServerError::InvalidBundle(format!(
+                "Version {} of {} is active; roll back or delete the bundle instead",
+                version, name
+            )));
+        }
+
+        fs::remove_dir_all(self.version_dir(name, version))?;
+        info!("Deleted bundle {} version {}", name, version);
+        Ok(())
+    }
+
+    /// Stop a bundle and delete all of its versions and storage
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        validate_name(name)?;
+        let _changes = self.changes.lock().await;
+
+        let dir = self.bundle_dir(name);
+        if !dir.is_dir() {
+            return Err(ServerError::BundleNotFound(name.to_string()));
+        }
+
+        // Calls already dispatched keep their worker until they finish
+        self.active.write().unwrap().remove(name);
+        fs::remove_dir_all(&dir)?;
+        AppStorage::new(&self.data_dir, StorageScope::Bundle(name.to_string())).remove()?;
+        info!("Deleted bundle {}", name);
+        Ok(())
+    }
+
+    /// Start a pool for a stored version and swap it in as the active one
+    async fn activate(&self, name: &str, version: u32) -> Result<()> {
+        let manifest = self.manifest(name, version)?;
+        let settings = &manifest.settings;
+        let permissions = settings.permissions.to_deno(&self.runtime.secret_names())?;
+
+        // Versions stored before the limits were lowered get the current maximums
+        let timeout = Duration::from_secs(settings.timeout_secs).min(self.limits.max_timeout);
+        let max_heap_mb = settings.max_heap_mb.min(self.limits.max_heap_mb);
+
+        let runtime = Arc::new(self.runtime.for_bundle(
+            name,
+            self.version_dir(name, version),
+            self.version_dir(name, version).join(&manifest.main),
+            permissions,
+            Some(timeout),
+            Some(max_heap_mb),
+            settings.kv,
+        ));
+        let exports = runtime.exports().clone();
+        let pool = DenoPool::new(runtime, settings.workers).await?;
+
+        // A bundle whose isolates cannot even load is rejected rather than retried forever
+        if pool.ready_count() == 0 {
+            let reason = pool
+                .stats()
+                .workers
+                .into_iter()
+                .find_map(|worker| worker.last_error)
+                .unwrap_or_else(|| "no isolate started".to_string());
+            return Err(ServerError::InvalidBundle(format!("{} version {} failed to load: {}", name, version, reason)));
+        }
+
+        fs::write(self.bundle_dir(name).join(ACTIVE_FILE), version.to_string())?;
+        self.active
+            .write()
+            .unwrap()
+            .insert(name.to_string(), Arc::new(ActiveBundle { manifest, pool, exports }));
+        Ok(())
+    }
+
+    fn bundle_dir(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    fn version_dir(&self, name: &str, version: u32) -> PathBuf {
+        self.bundle_dir(name).join(version.to_string())
+    }
+
+    fn manifest(&self, name: &str, version: u32) -> Result<BundleManifest> {
+        let path = self.version_dir(name, version).join(MANIFEST_FILE);
+        let contents = fs::read(&path)?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| ServerError::InvalidBundle(format!("Invalid manifest {:?}: {}", path, e)))
+    }
+
+    fn active_version(&self, name: &str) -> Result<Option<u32>> {
+        match fs::read_to_string(self.bundle_dir(name).join(ACTIVE_FILE)) {
+            Ok(version) => Ok(version.trim().parse().ok()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn latest_version(&self, name: &str) -> Result<u32> {
+        match fs::read_to_string(self.bundle_dir(name).join(LATEST_FILE)) {
+            Ok(version) => Ok(version.trim().parse().unwrap_or(0)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Names of the stored bundles, sorted
+    fn names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() && validate_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Stored versions of a bundle, in ascending order
+    fn versions(&self, name: &str) -> Result<Vec<u32>> {
+        let entries = match fs::read_dir(self.bundle_dir(name)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut versions = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            match entry.file_name().to_string_lossy().parse::<u32>() {
+                Ok(version) if entry.file_type()?.is_dir() => versions.push(version),
+                _ => {}
+            }
+        }
+        versions.sort_unstable();
+        Ok(versions)
+    }
+}
+
+fn write_version(dir: &Path, files: &BTreeMap<String, String>, manifest: &BundleManifest) -> Result<()> {
+    for (path, contents) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    fs::write(dir.join(MANIFEST_FILE), serde_json::to_vec_pretty(manifest)?)?;
+    Ok(())
+}
+
+/// Bundle names are lowercase letters, digits, `-` and `_`, starting with a letter or digit
+fn validate_name(name: &str) -> Result<()> {
+    let valid = name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ServerError::InvalidBundle(format!("Invalid bundle name: {:?}", name)))
+    }
+}
+
+fn validate_upload(upload: &BundleUpload, limits: &BundleLimits) -> Result<()> {
+    let invalid = |reason: String| Err(ServerError::InvalidBundle(reason));
+
+    if upload.files.is_empty() {
+        return invalid("A bundle needs at least one file".to_string());
+    }
+    for path in upload.files.keys() {
+        let relative = Path::new(path).components().all(|c| matches!(c, Component::Normal(_)));
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or_default();
+        if path.is_empty() || !relative || path.contains('\\') {
+            return invalid(format!("File path {:?} must be relative and stay inside the bundle", path));
+        }
+        if !BUNDLE_EXTENSIONS.contains(&extension) {
+            return invalid(format!("File {:?} must have one of the extensions {:?}", path, BUNDLE_EXTENSIONS));
+        }
+        if path.as_str() == MANIFEST_FILE {
+            return invalid(format!("{} is reserved for the bundle manifest", MANIFEST_FILE));
+        }
+    }
+    if !upload.files.contains_key(&upload.main) {
+        return invalid(format!("Entry module {:?} is not one of the uploaded files", upload.main));
+    }
+
+    let settings = &upload.settings;
+    if settings.workers == 0 || settings.workers > MAX_BUNDLE_WORKERS {
+        return invalid(format!("workers must be between 1 and {}", MAX_BUNDLE_WORKERS));
+    }
+    let max_timeout_secs = limits.max_timeout.as_secs();
+    if settings.timeout_secs == 0 || settings.timeout_secs > max_timeout_secs {
+        return invalid(format!("timeout_secs must be between 1 and {}", max_timeout_secs));
+    }
+    if settings.max_heap_mb < MIN_HEAP_MB || settings.max_heap_mb > limits.max_heap_mb {
+        return invalid(format!("max_heap_mb must be between {} and {}", MIN_HEAP_MB, limits.max_heap_mb));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: BundleLimits = BundleLimits {
+        max_timeout: Duration::from_secs(300),
+        max_heap_mb: 1024,
+    };
+
+    fn upload(body: serde_json::Value) -> BundleUpload {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn main_only(settings: serde_json::Value) -> BundleUpload {
+        let mut body = serde_json::json!({ "files": { "main.ts": "export function f() {}" } });
+        body.as_object_mut().unwrap().extend(settings.as_object().unwrap().clone());
+        upload(body)
+    }
+
+    #[test]
+    fn bundle_names_are_lowercase_slugs() {
+        let longest = "a".repeat(64);
+        let too_long = "a".repeat(65);
+        for name in ["slugify", "a", "0day", "text-tools", "text_tools_2", longest.as_str()] {
+            assert!(validate_name(name).is_ok(), "{:?} should be accepted", name);
+        }
+        for name in ["", "Slugify", "-tools", "_tools", "text.tools", "text/tools", "..", "tëxt", too_long.as_str()] {
+            assert!(matches!(validate_name(name), Err(ServerError::InvalidBundle(_))), "{:?} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn uploads_within_the_limits_are_accepted() {
+        let cases = [
+            serde_json::json!({}),
+            serde_json::json!({ "workers": MAX_BUNDLE_WORKERS, "timeout_secs": 300, "max_heap_mb": 1024 }),
+            serde_json::json!({ "timeout_secs": 1, "max_heap_mb": MIN_HEAP_MB }),
+        ];
+        for settings in cases {
+            assert!(validate_upload(&main_only(settings.clone()), &LIMITS).is_ok(), "{} should be accepted", settings);
+        }
+
+        let nested = upload(serde_json::json!({
+            "files": { "src/main.ts": "export * from './lib/util.ts';", "src/lib/util.ts": "", "data.json": "{}" },
+            "main": "src/main.ts",
+        }));
+        assert!(validate_upload(&nested, &LIMITS).is_ok());
+    }
+
+    #[test]
+    fn uploads_outside_the_limits_are_rejected() {
+        let cases = [
+            (serde_json::json!({ "workers": 0 }), "workers"),
+            (serde_json::json!({ "workers": MAX_BUNDLE_WORKERS + 1 }), "workers"),
+            (serde_json::json!({ "timeout_secs": 0 }), "timeout_secs"),
+            (serde_json::json!({ "timeout_secs": 301 }), "timeout_secs"),
+            (serde_json::json!({ "timeout_secs": u64::MAX }), "timeout_secs"),
+            (serde_json::json!({ "max_heap_mb": MIN_HEAP_MB - 1 }), "max_heap_mb"),
+            (serde_json::json!({ "max_heap_mb": 1025 }), "max_heap_mb"),
+            (serde_json::json!({ "max_heap_mb": usize::MAX }), "max_heap_mb"),
+        ];
+        for (settings, field) in cases {
+            match validate_upload(&main_only(settings.clone()), &LIMITS) {
+                Err(ServerError::InvalidBundle(reason)) => assert!(reason.contains(field), "{}: {}", settings, reason),
+                other => panic!("{} should be rejected, got {:?}", settings, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn uploads_with_unsafe_files_are_rejected() {
+        let cases = [
+            serde_json::json!({ "files": {} }),
+            serde_json::json!({ "files": { "main.ts": "" }, "main": "other.ts" }),
+            serde_json::json!({ "files": { "main.ts": "", "../escape.ts": "" } }),
+            serde_json::json!({ "files": { "main.ts": "", "/etc/passwd.ts": "" } }),
+            serde_json::json!({ "files": { "main.ts": "", "lib\\util.ts": "" } }),
+            serde_json::json!({ "files": { "main.ts": "", "native.so": "" } }),
+            serde_json::json!({ "files": { "main.ts": "", "README": "" } }),
+            serde_json::json!({ "files": { "main.ts": "", "bundle.json": "{}" } }),
+        ];
+        for body in cases {
+            assert!(
+                matches!(validate_upload(&upload(body.clone()), &LIMITS), Err(ServerError::InvalidBundle(_))),
+                "{} should be rejected",
+                body
+            );
+        }
+    }
+}
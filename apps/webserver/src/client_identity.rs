@@ -0,0 +1,241 @@
+use std::any::Any;
+use std::future::{ready, Ready};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::{Extensions, Payload, ServiceRequest};
+use actix_web::error::ErrorUnauthorized;
+use actix_web::rt::net::TcpStream;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use serde::Serialize;
+use tracing::warn;
+use x509_parser::extensions::GeneralName;
+
+/// Caller authenticated by a client certificate that the listener verified against the client CA bundle
+///
+/// Handlers take it as `Option<ClientIdentity>`; taking it directly rejects callers without a
+/// certificate with `401 Unauthorized`. Deno applications read it as `Webserver.context.client`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientIdentity {
+    /// Distinguished name of the certificate subject, such as `CN=billing, O=Example`
+    pub subject: String,
+
+    /// Common name of the subject
+    pub common_name: Option<String>,
+
+    /// DNS names, URIs, email and IP addresses from the subject alternative names
+    pub sans: Vec<String>,
+
+    /// Serial number of the certificate, as colon-separated hex bytes
+    pub serial: String,
+}
+
+impl ClientIdentity {
+    /// Read the identity from a DER-encoded certificate
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| format!("Invalid client certificate: {}", e))?;
+
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+
+        let mut sans = Vec::new();
+        if let Ok(Some(extension)) = cert.subject_alternative_name() {
+            for name in &extension.value.general_names {
+                match name {
+                    GeneralName::DNSName(name) | GeneralName::URI(name) | GeneralName::RFC822Name(name) => {
+                        sans.push(name.to_string());
+                    }
+                    GeneralName::IPAddress(bytes) => {
+                        if let Ok(octets) = <[u8; 4]>::try_from(*bytes) {
+                            sans.push(Ipv4Addr::from(octets).to_string());
+                        } else if let Ok(octets) = <[u8; 16]>::try_from(*bytes) {
+                            sans.push(Ipv6Addr::from(octets).to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            common_name,
+            sans,
+            serial: cert.raw_serial_as_string(),
+        })
+    }
+
+    /// Whether the subject or common name matches one of `subjects`, or a SAN one of `sans`
+    ///
+    /// Patterns may use `*` for any run of characters. With no patterns at all, every
+    /// identity is allowed.
+    pub fn is_allowed(&self, subjects: &[String], sans: &[String]) -> bool {
+        if subjects.is_empty() && sans.is_empty() {
+            return true;
+        }
+
+        let subject_allowed = subjects.iter().any(|pattern| {
+            pattern_matches(pattern, &self.subject)
+                || self.common_name.as_deref().is_some_and(|cn| pattern_matches(pattern, cn))
+        });
+        let san_allowed = sans
+            .iter()
+            .any(|pattern| self.sans.iter().any(|san| pattern_matches(pattern, san)));
+        subject_allowed || san_allowed
+    }
+
+    /// Copy the identity verified for the connection into the extensions of `req`
+    pub fn assign(req: &ServiceRequest) {
+        let identity = req.request().conn_data::<ClientIdentity>().cloned();
+        if let Some(identity) = identity {
+            req.extensions_mut().insert(identity);
+        }
+    }
+}
+
+impl FromRequest for ClientIdentity {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let identity = req
+            .extensions()
+            .get::<ClientIdentity>()
+            .cloned()
+            .ok_or_else(|| ErrorUnauthorized("Client certificate required"));
+        ready(identity)
+    }
+}
+
+/// Store the identity of a client certificate with its connection, for `ClientIdentity::assign`
+///
+/// Passed to `HttpServer::on_connect`. Plain HTTP connections and TLS clients without a
+/// certificate get no identity.
+pub fn on_connect(conn: &dyn Any, data: &mut Extensions) {
+    let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let Some(cert) = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) else {
+        return;
+    };
+
+    match ClientIdentity::from_der(cert) {
+        Ok(identity) => {
+            data.insert(identity);
+        }
+        Err(err) => warn!("{}", err),
+    }
+}
+
+/// Match `value` against `pattern`, where `*` stands for any run of characters
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> ClientIdentity {
+        ClientIdentity {
+            subject: "CN=billing, O=Example".to_string(),
+            common_name: Some("billing".to_string()),
+            sans: vec!["billing.internal.example.com".to_string(), "10.0.0.7".to_string()],
+            serial: "01:02".to_string(),
+        }
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn patterns_without_a_star_match_exactly() {
+        assert!(pattern_matches("billing", "billing"));
+        assert!(!pattern_matches("billing", "billing2"));
+        assert!(!pattern_matches("billing", "bill"));
+        assert!(pattern_matches("", ""));
+        assert!(!pattern_matches("", "billing"));
+    }
+
+    #[test]
+    fn stars_match_any_run_of_characters() {
+        // Leading
+        assert!(pattern_matches("*.example.com", "api.example.com"));
+        assert!(!pattern_matches("*.example.com", "example.com"));
+        // Trailing
+        assert!(pattern_matches("billing-*", "billing-eu"));
+        assert!(pattern_matches("billing-*", "billing-"));
+        assert!(!pattern_matches("billing-*", "shipping-eu"));
+        // Middle
+        assert!(pattern_matches("spiffe://*/billing", "spiffe://prod.example.com/billing"));
+        assert!(!pattern_matches("spiffe://*/billing", "spiffe://prod.example.com/shipping"));
+        // Alone
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("*", "anything"));
+    }
+
+    #[test]
+    fn repeated_segments_need_separate_matches() {
+        assert!(pattern_matches("a*bc*bc", "abcbc"));
+        assert!(pattern_matches("a*bc*bc", "axbcybc"));
+        assert!(!pattern_matches("a*bc*bc", "abc"));
+        assert!(!pattern_matches("a*bc*bc", "abcb"));
+        // The prefix and suffix may not overlap
+        assert!(!pattern_matches("ab*b", "ab"));
+    }
+
+    #[test]
+    fn every_identity_is_allowed_without_patterns() {
+        assert!(identity().is_allowed(&[], &[]));
+    }
+
+    #[test]
+    fn subjects_match_the_common_name_or_the_full_name() {
+        let identity = identity();
+        assert!(identity.is_allowed(&patterns(&["billing"]), &[]));
+        assert!(identity.is_allowed(&patterns(&["CN=billing, O=Example"]), &[]));
+        assert!(identity.is_allowed(&patterns(&["CN=*, O=Example"]), &[]));
+        assert!(!identity.is_allowed(&patterns(&["CN=billing"]), &[]));
+        assert!(!identity.is_allowed(&patterns(&["shipping"]), &[]));
+
+        let without_cn = ClientIdentity {
+            common_name: None,
+            ..identity
+        };
+        assert!(!without_cn.is_allowed(&patterns(&["billing"]), &[]));
+    }
+
+    #[test]
+    fn sans_match_any_alternative_name() {
+        let identity = identity();
+        assert!(identity.is_allowed(&[], &patterns(&["*.internal.example.com"])));
+        assert!(identity.is_allowed(&[], &patterns(&["10.0.0.*"])));
+        assert!(!identity.is_allowed(&[], &patterns(&["*.public.example.com"])));
+        // Either list may admit the identity
+        assert!(identity.is_allowed(&patterns(&["shipping"]), &patterns(&["10.0.0.7"])));
+    }
+}
@@ -1,8 +1,24 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 use clap::Parser;
-use dotenv::dotenv;
-use std::env;
+use std::collections::HashMap;
+
+use crate::routes::{self, MountConfig};
+use crate::secrets::{ChainedSecrets, Secret, SecretsProvider};
+use crate::settings::{AiSettings, PermissionSettings, Settings, TlsSettings};
+
+/// Secret holding the comma-separated bearer tokens of the admin API
+const ADMIN_KEYS_SECRET: &str = "ADMIN_API_KEYS";
+
+/// Address the Deno inspector listens on when `--inspect` is given without one
+const DEFAULT_INSPECT_ADDR: &str = "127.0.0.1:9229";
+
+/// Smallest heap limit, in megabytes, that leaves V8 room to boot an isolate
+pub const MIN_HEAP_MB: usize = 16;
+
+/// Log levels accepted by `--log-level` and `server.log_level`
+const LOG_LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -10,88 +26,634 @@ pub struct ServerConfig {
     /// Address to bind the server to
     pub addr: SocketAddr,
     
+    /// HTTPS settings; plain HTTP is served on `addr` without them
+    pub tls: Option<TlsConfig>,
+    
     /// Path to the static files directory (React/Vite build output)
     pub static_dir: PathBuf,
     
     /// Number of worker threads to use
     pub worker_threads: usize,
     
+    /// Number of Deno isolates in the worker pool
+    pub deno_workers: usize,
+    
+    /// Maximum time a single Deno call may run before the isolate is terminated
+    pub deno_call_timeout: Option<Duration>,
+    
+    /// Maximum time a streaming Deno call may run before the isolate is terminated
+    pub deno_stream_timeout: Option<Duration>,
+    
+    /// V8 heap limit for each Deno isolate, in megabytes
+    pub deno_max_heap_mb: Option<usize>,
+    
     /// Log level
     pub log_level: String,
     
-    /// OpenAI API key for Deno application
-    pub openai_api_key: String,
+    /// Configuration file the settings were layered from
+    pub config_file: Option<PathBuf>,
+    
+    /// Largest JSON body accepted by the API, in bytes
+    pub max_json_bytes: usize,
+    
+    /// Largest bundle accepted by the admin API, in bytes
+    pub max_upload_bytes: usize,
+    
+    /// Longest call timeout a bundle upload may set
+    pub bundle_max_timeout: Duration,
+    
+    /// Largest V8 heap limit a bundle upload may set, in megabytes
+    pub bundle_max_heap_mb: usize,
+    
+    /// Time open streams and in-flight calls get to finish once shutdown starts
+    pub shutdown_grace: Duration,
+    
+    /// Source of answers to copilot requests
+    pub ai_backend: AiBackendConfig,
+    
+    /// Secrets exposed to the Deno application through `Webserver.secrets`, by name
+    pub secrets: HashMap<String, Secret>,
     
     /// Path to the Deno application
     pub deno_app_path: PathBuf,
+    
+    /// Directory for transpiled module output
+    pub deno_cache_dir: PathBuf,
+    
+    /// Directory holding vendored remote, npm and jsr modules
+    pub deno_vendor_dir: Option<PathBuf>,
+    
+    /// Boot Deno workers from a cached V8 startup snapshot
+    pub deno_snapshot: bool,
+    
+    /// Reload the Deno application when its modules change
+    pub watch: bool,
+    
+    /// Permissions granted to the Deno application
+    pub deno_permissions: DenoPermissions,
+    
+    /// Chrome DevTools inspector for the Deno runtime
+    pub deno_inspect: Option<DenoInspect>,
+    
+    /// Route table file the mounts were loaded from
+    pub routes_file: Option<PathBuf>,
+    
+    /// Deno modules mounted on their own routes, each with its own workers
+    pub mounts: Vec<MountConfig>,
+    
+    /// Directory for persistent server data: bundles and per-application storage
+    pub data_dir: PathBuf,
+    
+    /// Enable Deno's unstable APIs, which include `Deno.openKv()`; the runtime has no
+    /// switch for KV alone, so every other unstable API is enabled with it
+    pub deno_kv: bool,
+    
+    /// Directory storing function bundles deployed through the admin API
+    pub bundles_dir: PathBuf,
+    
+    /// Bearer tokens accepted by the admin API; it rejects every request when empty
+    pub admin_keys: Vec<Secret>,
+}
+
+/// Source of answers to copilot requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AiBackendConfig {
+    /// The Deno application at `DENO_APP_PATH`, run in the server's own isolates
+    Deno,
+    
+    /// The Node.js AI service at `url`
+    Http { url: String },
+    
+    /// Canned in-process answers, for tests and frontend development
+    Mock,
+}
+
+impl AiBackendConfig {
+    /// Build the backend selection from the `[ai]` settings
+    fn from_settings(settings: &AiSettings) -> Result<Self, String> {
+        match settings.backend.as_deref().unwrap_or_default() {
+            "deno" if cfg!(feature = "deno") => Ok(Self::Deno),
+            "deno" => Err("The deno AI backend requires building with the deno feature".to_string()),
+            "http" => {
+                let url = settings.service_url.clone().unwrap_or_default();
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(format!("Invalid AI service URL: {:?}", url));
+                }
+                Ok(Self::Http { url })
+            }
+            "mock" => Ok(Self::Mock),
+            other => Err(format!("Unknown AI backend {:?}; expected deno, http or mock", other)),
+        }
+    }
+}
+
+/// HTTPS listener settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM certificate chain, reloaded when the file changes
+    pub cert_file: PathBuf,
+    
+    /// PEM private key, reloaded when the file changes
+    pub key_file: PathBuf,
+    
+    /// Address of a plain HTTP listener that redirects to HTTPS
+    pub redirect_addr: Option<SocketAddr>,
+    
+    /// `max-age` of the Strict-Transport-Security header, which is omitted when `None`
+    pub hsts_max_age: Option<Duration>,
+    
+    /// Client certificate verification; clients are not asked for a certificate without it
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+/// Mutual TLS settings for callers that authenticate with client certificates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientAuthConfig {
+    /// PEM bundle of the CAs client certificates must chain to
+    pub ca_file: PathBuf,
+    
+    /// Refuse connections without a client certificate
+    pub required: bool,
+    
+    /// Patterns for the subject or common name of accepted certificates
+    pub allowed_subjects: Vec<String>,
+    
+    /// Patterns for the subject alternative names of accepted certificates
+    pub allowed_sans: Vec<String>,
+}
+
+impl TlsConfig {
+    /// Build the HTTPS settings from `[tls]`, or `None` when no certificate is configured
+    fn from_settings(settings: &TlsSettings) -> Result<Option<Self>, String> {
+        let hsts_max_age = settings.hsts_max_age_secs.unwrap_or_default();
+        let (cert_file, key_file) = match (&settings.cert_file, &settings.key_file) {
+            (Some(cert_file), Some(key_file)) => (cert_file.clone(), key_file.clone()),
+            (None, None) if settings.redirect_addr.is_some() || hsts_max_age > 0 || settings.client_ca_file.is_some() => {
+                return Err("tls.redirect_addr, tls.hsts_max_age_secs and tls.client_ca_file require a TLS certificate".to_string());
+            }
+            (None, None) => return Ok(None),
+            _ => return Err("tls.cert_file and tls.key_file must be set together".to_string()),
+        };
+        
+        if !cert_file.is_file() {
+            return Err(format!("TLS certificate does not exist: {:?}", cert_file));
+        }
+        if !key_file.is_file() {
+            return Err(format!("TLS private key does not exist: {:?}", key_file));
+        }
+        let redirect_addr = settings
+            .redirect_addr
+            .as_deref()
+            .map(|addr| addr.parse::<SocketAddr>().map_err(|e| format!("Invalid TLS redirect address: {}", e)))
+            .transpose()?;
+        
+        let allowed_subjects = settings.client_allowed_subjects.clone().unwrap_or_default();
+        let allowed_sans = settings.client_allowed_sans.clone().unwrap_or_default();
+        let client_auth = match &settings.client_ca_file {
+            Some(ca_file) if !ca_file.is_file() => {
+                return Err(format!("TLS client CA bundle does not exist: {:?}", ca_file));
+            }
+            Some(ca_file) => Some(ClientAuthConfig {
+                ca_file: ca_file.clone(),
+                required: settings.client_auth_required.unwrap_or_default(),
+                allowed_subjects,
+                allowed_sans,
+            }),
+            None if settings.client_auth_required == Some(true) || !allowed_subjects.is_empty() || !allowed_sans.is_empty() => {
+                return Err("tls.client_auth_required and the allowed client patterns require tls.client_ca_file".to_string());
+            }
+            None => None,
+        };
+        
+        Ok(Some(Self {
+            cert_file,
+            key_file,
+            redirect_addr,
+            hsts_max_age: (hsts_max_age > 0).then(|| Duration::from_secs(hsts_max_age)),
+            client_auth,
+        }))
+    }
+}
+
+/// Chrome DevTools inspector settings for the Deno runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenoInspect {
+    /// Address the inspector server listens on
+    pub addr: SocketAddr,
+    
+    /// Pause before the first statement of the Deno application
+    pub break_on_start: bool,
+    
+    /// Wait for a debugger to attach before running the Deno application
+    pub wait_for_session: bool,
+}
+
+impl DenoInspect {
+    /// Build inspector settings from `--inspect`, `--inspect-brk` or `--inspect-wait`
+    pub(crate) fn from_args(args: &Args) -> Option<Self> {
+        if let Some(addr) = args.inspect_brk {
+            return Some(Self { addr, break_on_start: true, wait_for_session: true });
+        }
+        if let Some(addr) = args.inspect_wait {
+            return Some(Self { addr, break_on_start: false, wait_for_session: true });
+        }
+        args.inspect.map(|addr| Self { addr, break_on_start: false, wait_for_session: false })
+    }
+}
+
+/// Permissions granted to the Deno application
+///
+/// Each list follows Deno's `--allow-*` semantics: `None` denies the permission,
+/// an empty list allows everything, and a non-empty list allows only those entries.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DenoPermissions {
+    /// Paths that may be read
+    pub allow_read: Option<Vec<PathBuf>>,
+    
+    /// Paths that may be written
+    pub allow_write: Option<Vec<PathBuf>>,
+    
+    /// Environment variables that may be read or set
+    pub allow_env: Option<Vec<String>>,
+    
+    /// Hosts (with optional port) that may be reached over the network
+    pub allow_net: Option<Vec<String>>,
+    
+    /// Programs that may be spawned
+    pub allow_run: Option<Vec<String>>,
+    
+    /// Dynamic libraries that may be loaded
+    pub allow_ffi: Option<Vec<PathBuf>>,
+}
+
+impl DenoPermissions {
+    /// Convert the `[deno.permissions]` lists, where `["*"]` allows everything
+    fn from_settings(settings: &PermissionSettings) -> Self {
+        Self {
+            allow_read: permission_list(&settings.allow_read).map(|list| list.into_iter().map(PathBuf::from).collect()),
+            allow_write: permission_list(&settings.allow_write).map(|list| list.into_iter().map(PathBuf::from).collect()),
+            allow_env: permission_list(&settings.allow_env),
+            allow_net: permission_list(&settings.allow_net),
+            allow_run: permission_list(&settings.allow_run),
+            allow_ffi: permission_list(&settings.allow_ffi).map(|list| list.into_iter().map(PathBuf::from).collect()),
+        }
+    }
+}
+
+/// Convert an allow list to Deno's semantics: `None` denies and an empty list allows everything
+pub(crate) fn permission_list(list: &Option<Vec<String>>) -> Option<Vec<String>> {
+    match list.as_deref() {
+        None | Some([]) => None,
+        Some(list) if list.iter().any(|entry| entry == "*") => Some(vec![]),
+        Some(list) => Some(list.to_vec()),
+    }
 }
 
 /// Command line arguments
-#[derive(Parser, Debug)]
+///
+/// Every setting given here overrides the configuration file and the environment.
+#[derive(Parser, Debug, Clone)]
 #[clap(author, version, about)]
 pub struct Args {
-    /// Address to bind the server to
-    #[clap(short, long, default_value = "127.0.0.1:3000")]
-    pub addr: String,
+    /// TOML or YAML configuration file (default: WEBSERVER_CONFIG)
+    #[clap(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
     
-    /// Path to the static files directory (React/Vite build output)
-    #[clap(short, long, default_value = "dist")]
-    pub static_dir: PathBuf,
+    /// Print the resolved configuration with secrets redacted, report every validation error, then exit
+    #[clap(long)]
+    pub check_config: bool,
     
-    /// Number of worker threads to use (0 means use number of CPU cores)
-    #[clap(short, long, default_value = "0")]
-    pub worker_threads: usize,
+    /// Address to bind the server to (default: 127.0.0.1:3000)
+    #[clap(short, long)]
+    pub addr: Option<String>,
     
-    /// Log level (trace, debug, info, warn, error)
-    #[clap(short, long, default_value = "info")]
-    pub log_level: String,
+    /// Path to the static files directory, the React/Vite build output (default: dist)
+    #[clap(short, long)]
+    pub static_dir: Option<PathBuf>,
+    
+    /// Number of worker threads to use (default: 0, which means use number of CPU cores)
+    #[clap(short, long)]
+    pub worker_threads: Option<usize>,
+    
+    /// Number of Deno isolates in the worker pool (default: 0, which means use the number of worker threads)
+    #[clap(short, long)]
+    pub deno_workers: Option<usize>,
+    
+    /// Maximum seconds a Deno call may run before it is terminated, 0 disables the limit (default: 120)
+    #[clap(long)]
+    pub deno_call_timeout: Option<u64>,
+    
+    /// Maximum seconds a streaming Deno call may run before it is terminated, 0 disables the limit (default: 3600)
+    #[clap(long)]
+    pub deno_stream_timeout: Option<u64>,
+    
+    /// V8 heap limit for each Deno isolate in megabytes, 0 uses the V8 default (default: 0)
+    #[clap(long)]
+    pub deno_max_heap_mb: Option<usize>,
+    
+    /// Directory for transpiled TypeScript output (default: .cache/deno)
+    #[clap(long)]
+    pub deno_cache_dir: Option<PathBuf>,
+    
+    /// Directory holding vendored remote, npm and jsr modules (remote imports are rejected without it)
+    #[clap(long)]
+    pub deno_vendor_dir: Option<PathBuf>,
+    
+    /// Boot Deno workers from a V8 startup snapshot of the evaluated application, cached in the cache directory
+    #[clap(long)]
+    pub deno_snapshot: bool,
+    
+    /// Reload the Deno application when any of its modules change (development only)
+    #[clap(long)]
+    pub watch: bool,
+    
+    /// Start the Chrome DevTools inspector for the Deno application on HOST:PORT
+    #[clap(long, value_name = "HOST:PORT", num_args = 0..=1, default_missing_value = DEFAULT_INSPECT_ADDR, conflicts_with_all = ["inspect_brk", "inspect_wait"])]
+    pub inspect: Option<SocketAddr>,
+    
+    /// Like --inspect, but pause before the first statement of the Deno application
+    #[clap(long, value_name = "HOST:PORT", num_args = 0..=1, default_missing_value = DEFAULT_INSPECT_ADDR, conflicts_with = "inspect_wait")]
+    pub inspect_brk: Option<SocketAddr>,
+    
+    /// Like --inspect, but wait for a debugger to attach before running the Deno application
+    #[clap(long, value_name = "HOST:PORT", num_args = 0..=1, default_missing_value = DEFAULT_INSPECT_ADDR)]
+    pub inspect_wait: Option<SocketAddr>,
+    
+    /// JSON route table mounting additional Deno modules on their own paths
+    #[clap(long, value_name = "FILE")]
+    pub routes: Option<PathBuf>,
+    
+    /// Directory for persistent server data: deployed bundles and each application's Cache API, localStorage and KV files (default: .data)
+    #[clap(long)]
+    pub data_dir: Option<PathBuf>,
+    
+    /// Enable Deno KV (`Deno.openKv()`), stored in a SQLite file in each application's storage directory; this turns on all of Deno's unstable APIs
+    #[clap(long)]
+    pub deno_kv: bool,
+    
+    /// Directory storing function bundles deployed through the admin API (default: DATA_DIR/bundles)
+    #[clap(long)]
+    pub bundles_dir: Option<PathBuf>,
+    
+    /// Source of copilot answers: deno (embedded runtime), http (Node.js AI service) or mock (default: deno with the deno feature, http otherwise)
+    #[clap(long)]
+    pub ai_backend: Option<String>,
+    
+    /// Base URL of the Node.js AI service for the http backend (default: AI_SERVICE_URL or http://127.0.0.1:3001)
+    #[clap(long, value_name = "URL")]
+    pub ai_service_url: Option<String>,
+    
+    /// Encrypt a JSON object of secrets to FILE.enc with the key in SECRETS_KEY or SECRETS_KEY_FILE, then exit
+    #[clap(long, value_name = "FILE")]
+    pub seal_secrets: Option<PathBuf>,
+    
+    /// Log level: trace, debug, info, warn or error (default: info)
+    #[clap(short, long)]
+    pub log_level: Option<String>,
+    
+    /// Seconds open streams and in-flight Deno calls get to finish after SIGTERM or SIGINT (default: 30)
+    #[clap(long, value_name = "SECS")]
+    pub shutdown_grace: Option<u64>,
+    
+    /// PEM certificate chain; serves HTTPS when given with --tls-key, reloading both when they change
+    #[clap(long, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    
+    /// PEM private key for --tls-cert
+    #[clap(long, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    
+    /// Also listen for plain HTTP on HOST:PORT, redirecting every request to HTTPS
+    #[clap(long, value_name = "HOST:PORT")]
+    pub tls_redirect_addr: Option<String>,
+    
+    /// Send Strict-Transport-Security with this max-age in seconds over HTTPS, 0 to omit it (default: 0)
+    #[clap(long, value_name = "SECS")]
+    pub hsts_max_age: Option<u64>,
+    
+    /// PEM bundle of the CAs that issue client certificates, enabling mutual TLS
+    #[clap(long, value_name = "FILE")]
+    pub tls_client_ca: Option<PathBuf>,
+    
+    /// Refuse TLS connections that present no client certificate
+    #[clap(long, requires = "tls_client_ca")]
+    pub tls_client_auth_required: bool,
 }
 
 impl ServerConfig {
-    /// Create a new server configuration from command line arguments
+    /// Create a new server configuration from command line arguments, the environment and
+    /// the configuration file they name
     pub fn from_args(args: Args) -> Result<Self, String> {
-        // Load environment variables from .env file
-        dotenv().ok();
+        let settings = Settings::load(&args).map_err(|errors| errors.join("; "))?;
+        Self::from_settings(&settings, Settings::config_file(&args)).map_err(|errors| errors.join("; "))
+    }
+    
+    /// Settings that differ from `other` but only take effect after a restart
+    pub fn restart_required(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("server.addr", self.addr != other.addr),
+            ("tls", self.tls != other.tls),
+            ("server.worker_threads", self.worker_threads != other.worker_threads),
+            ("server.data_dir", self.data_dir != other.data_dir),
+            ("server.shutdown_grace_secs", self.shutdown_grace != other.shutdown_grace),
+            ("static_files.dir", self.static_dir != other.static_dir),
+            ("ai", self.ai_backend != other.ai_backend),
+            ("deno.app_path", self.deno_app_path != other.deno_app_path),
+            ("deno.workers", self.deno_workers != other.deno_workers),
+            ("deno.cache_dir", self.deno_cache_dir != other.deno_cache_dir),
+            ("deno.vendor_dir", self.deno_vendor_dir != other.deno_vendor_dir),
+            ("deno.snapshot", self.deno_snapshot != other.deno_snapshot),
+            ("deno.watch", self.watch != other.watch),
+            ("deno.kv", self.deno_kv != other.deno_kv),
+            ("deno.bundles_dir", self.bundles_dir != other.bundles_dir),
+            ("deno.secrets", self.secrets != other.secrets),
+            ("deno.permissions", self.deno_permissions != other.deno_permissions),
+            ("deno.inspect", self.deno_inspect != other.deno_inspect),
+            ("limits.deno_call_timeout_secs", self.deno_call_timeout != other.deno_call_timeout),
+            ("limits.deno_stream_timeout_secs", self.deno_stream_timeout != other.deno_stream_timeout),
+            ("limits.deno_max_heap_mb", self.deno_max_heap_mb != other.deno_max_heap_mb),
+            ("limits.bundle_max_timeout_secs", self.bundle_max_timeout != other.bundle_max_timeout),
+            ("limits.bundle_max_heap_mb", self.bundle_max_heap_mb != other.bundle_max_heap_mb),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+    
+    /// Validate layered settings, reporting every invalid setting at once
+    pub fn from_settings(settings: &Settings, config_file: Option<PathBuf>) -> Result<Self, Vec<String>> {
+        let mut errors = Vec::new();
         
         // Parse the address
-        let addr = args.addr.parse()
-            .map_err(|e| format!("Invalid address: {}", e))?;
+        let addr = settings.server.addr.as_deref().unwrap_or_default().parse::<SocketAddr>()
+            .map_err(|e| format!("Invalid address: {}", e));
+        let addr = check(&mut errors, addr);
+        
+        // Check the HTTPS settings
+        let tls = check(&mut errors, TlsConfig::from_settings(&settings.tls)).flatten();
+        
+        // Check the log level
+        let log_level = settings.server.log_level.clone().unwrap_or_default();
+        if !LOG_LEVELS.contains(&log_level.as_str()) {
+            errors.push(format!("Invalid log level {:?}; expected one of {}", log_level, LOG_LEVELS.join(", ")));
+        }
         
         // Determine the number of worker threads
-        let worker_threads = if args.worker_threads == 0 {
-            std::thread::available_parallelism()
+        let worker_threads = match settings.server.worker_threads.unwrap_or_default() {
+            0 => std::thread::available_parallelism()
                 .map(|n| n.get())
-                .unwrap_or(1)
-        } else {
-            args.worker_threads
+                .unwrap_or(1),
+            n => n,
+        };
+        
+        // Get the Deno inspector settings
+        let deno_inspect = settings.deno.inspect.clone();
+        
+        // Determine the size of the Deno worker pool; a debugged application runs in a single isolate by default
+        let deno_workers = match settings.deno.workers.unwrap_or_default() {
+            0 if deno_inspect.is_some() => 1,
+            0 => worker_threads,
+            n => n,
         };
         
         // Ensure the static directory exists
-        if !args.static_dir.exists() {
-            return Err(format!("Static directory does not exist: {:?}", args.static_dir));
+        let static_dir = settings.static_files.dir.clone().unwrap_or_default();
+        if !static_dir.exists() {
+            errors.push(format!("Static directory does not exist: {:?}", static_dir));
+        }
+        
+        // Get the AI backend
+        let ai_backend = check(&mut errors, AiBackendConfig::from_settings(&settings.ai));
+        let embedded = ai_backend == Some(AiBackendConfig::Deno);
+        
+        // Load the named secrets; the Deno backend always asks for OPENAI_API_KEY
+        let mut secret_names = settings.deno.secrets.clone().unwrap_or_default();
+        if embedded && !secret_names.iter().any(|name| name == "OPENAI_API_KEY") {
+            secret_names.push("OPENAI_API_KEY".to_string());
+        }
+        // Admin keys are never handed to the Deno application
+        secret_names.retain(|name| name != ADMIN_KEYS_SECRET);
+        let secrets_provider = check(&mut errors, ChainedSecrets::from_env().map_err(|e| e.to_string()));
+        let secrets = secrets_provider
+            .as_ref()
+            .and_then(|provider| check(&mut errors, provider.load(&secret_names).map_err(|e| e.to_string())))
+            .unwrap_or_default();
+        
+        // Load the comma-separated admin API keys, after those from the settings
+        let mut admin_keys = settings.auth.admin_keys.clone().unwrap_or_default();
+        if let Some(keys) = secrets_provider
+            .as_ref()
+            .and_then(|provider| check(&mut errors, provider.get(ADMIN_KEYS_SECRET).map_err(|e| e.to_string())))
+            .flatten()
+        {
+            admin_keys.extend(
+                keys.expose()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(Secret::new),
+            );
+        }
+        
+        // Ensure the Deno application exists when it answers copilot requests
+        let deno_app_path = settings.deno.app_path.clone().unwrap_or_default();
+        if embedded && !deno_app_path.exists() {
+            errors.push(format!("Deno application does not exist: {:?}", deno_app_path));
         }
         
-        // Get OpenAI API key from environment
-        let openai_api_key = env::var("OPENAI_API_KEY")
-            .map_err(|_| "OPENAI_API_KEY environment variable not set".to_string())?;
+        // Ensure the vendor directory exists
+        if let Some(dir) = &settings.deno.vendor_dir {
+            if !dir.is_dir() {
+                errors.push(format!("Deno vendor directory does not exist: {:?}", dir));
+            }
+        }
+        
+        // Load the route table
+        let mounts = match &settings.deno.routes {
+            Some(path) => check(&mut errors, routes::load(path).map_err(|e| e.to_string())).unwrap_or_default(),
+            None => Vec::new(),
+        };
+        
+        // Check the limits
+        let max_json_bytes = settings.limits.max_json_bytes.unwrap_or_default();
+        let max_upload_bytes = settings.limits.max_upload_bytes.unwrap_or_default();
+        if max_json_bytes == 0 {
+            errors.push("limits.max_json_bytes must be at least 1".to_string());
+        }
+        if max_upload_bytes == 0 {
+            errors.push("limits.max_upload_bytes must be at least 1".to_string());
+        }
+        let bundle_max_timeout_secs = settings.limits.bundle_max_timeout_secs.unwrap_or_default();
+        let bundle_max_heap_mb = settings.limits.bundle_max_heap_mb.unwrap_or_default();
+        if bundle_max_timeout_secs == 0 {
+            errors.push("limits.bundle_max_timeout_secs must be at least 1".to_string());
+        }
+        if bundle_max_heap_mb < MIN_HEAP_MB {
+            errors.push(format!("limits.bundle_max_heap_mb must be at least {}", MIN_HEAP_MB));
+        }
+        for (name, mb) in [
+            ("limits.deno_max_heap_mb", settings.limits.deno_max_heap_mb.unwrap_or_default()),
+            ("limits.bundle_max_heap_mb", bundle_max_heap_mb),
+        ] {
+            if mb.checked_mul(1024 * 1024).is_none() {
+                errors.push(format!("{} is too large", name));
+            }
+        }
         
-        // Get Deno application path
-        let deno_app_path = PathBuf::from(
-            env::var("DENO_APP_PATH").unwrap_or_else(|_| "../apps/ai-service/main.ts".to_string())
-        );
+        // Get Deno permissions
+        let deno_permissions = DenoPermissions::from_settings(&settings.deno.permissions);
         
-        // Ensure the Deno application exists
-        if !deno_app_path.exists() {
-            return Err(format!("Deno application does not exist: {:?}", deno_app_path));
+        let (Some(addr), Some(ai_backend)) = (addr, ai_backend) else {
+            return Err(errors);
+        };
+        if !errors.is_empty() {
+            return Err(errors);
         }
         
+        let deno_call_timeout = settings.limits.deno_call_timeout_secs.unwrap_or_default();
+        let deno_stream_timeout = settings.limits.deno_stream_timeout_secs.unwrap_or_default();
+        let deno_max_heap_mb = settings.limits.deno_max_heap_mb.unwrap_or_default();
+        let data_dir = settings.server.data_dir.clone().unwrap_or_default();
+        
         Ok(Self {
             addr,
-            static_dir: args.static_dir,
+            tls,
+            static_dir,
             worker_threads,
-            log_level: args.log_level,
-            openai_api_key,
+            deno_workers,
+            // Calls paused in the debugger must not be terminated
+            deno_call_timeout: (deno_call_timeout > 0 && deno_inspect.is_none())
+                .then(|| Duration::from_secs(deno_call_timeout)),
+            deno_stream_timeout: (deno_stream_timeout > 0 && deno_inspect.is_none())
+                .then(|| Duration::from_secs(deno_stream_timeout)),
+            deno_max_heap_mb: (deno_max_heap_mb > 0).then_some(deno_max_heap_mb),
+            log_level,
+            config_file,
+            max_json_bytes,
+            max_upload_bytes,
+            bundle_max_timeout: Duration::from_secs(bundle_max_timeout_secs),
+            bundle_max_heap_mb,
+            shutdown_grace: Duration::from_secs(settings.server.shutdown_grace_secs.unwrap_or_default()),
+            ai_backend,
+            secrets,
             deno_app_path,
+            deno_cache_dir: settings.deno.cache_dir.clone().unwrap_or_default(),
+            deno_vendor_dir: settings.deno.vendor_dir.clone(),
+            deno_snapshot: settings.deno.snapshot.unwrap_or_default(),
+            watch: settings.deno.watch.unwrap_or_default(),
+            deno_permissions,
+            deno_inspect,
+            routes_file: settings.deno.routes.clone(),
+            mounts,
+            bundles_dir: settings.deno.bundles_dir.clone().unwrap_or_else(|| data_dir.join("bundles")),
+            data_dir,
+            deno_kv: settings.deno.kv.unwrap_or_default(),
+            admin_keys,
         })
     }
+}
+
+/// Keep the value of a successful step, or record its error
+fn check<T>(errors: &mut Vec<String>, result: Result<T, String>) -> Option<T> {
+    result.map_err(|err| errors.push(err)).ok()
 }
\ No newline at end of file
@@ -1,177 +1,992 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self as std_mpsc, RecvTimeoutError};
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Duration;
 
-use deno_core::error::AnyError;
-use deno_core::{v8, JsRuntime, RuntimeOptions};
+use deno_core::error::{AnyError, JsError};
+use deno_core::futures::task::LocalFutureObj;
+use deno_core::{serde_v8, v8, JsRuntime, ModuleId, RuntimeOptions};
 use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
+use deno_runtime::deno_io::{Stdio, StdioPipe};
 use deno_runtime::deno_web::BlobStore;
-use deno_runtime::permissions::Permissions;
+use deno_runtime::inspector_server::InspectorServer;
+use deno_runtime::permissions::{Permissions, PermissionsContainer, PermissionsOptions};
+use deno_runtime::ops::worker_host::{CreateWebWorkerArgs, CreateWebWorkerCb, WorkerEventCb};
+use deno_runtime::web_worker::{SendableWebWorkerHandle, WebWorker, WebWorkerOptions};
 use deno_runtime::worker::{MainWorker, WorkerOptions};
 use deno_runtime::BootstrapOptions;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::warn;
 
 use crate::error::{Result, ServerError};
-use crate::config::ServerConfig;
+use crate::config::{DenoPermissions, ServerConfig};
+use crate::functions::{ExportedFunctions, FunctionRegistry};
+use crate::loader::{ModuleGraph, SourceMapStore, TsModuleLoader};
+use crate::ops::{log_output, webserver_ops, CallContext, CurrentCall, HostServices, SecretStore, StreamSink};
+use crate::snapshot::{SnapshotCache, SnapshotSource};
+use crate::storage::{AppStorage, StorageScope};
+use crate::vendor::Vendor;
 
 /// Deno runtime service for executing TypeScript/JavaScript code
 pub struct DenoRuntime {
     /// Configuration for the server
     config: Arc<ServerConfig>,
+    
+    /// Entry module of the application served by this runtime
+    main_module: PathBuf,
+    
+    /// Directory modules must be loaded from, for function bundles
+    module_root: Option<PathBuf>,
+    
+    /// Files loaded as modules by any worker
+    module_graph: ModuleGraph,
+    
+    /// Startup snapshot of the application, if enabled
+    snapshot: Option<SnapshotCache>,
+    
+    /// Chrome DevTools inspector server shared by all isolates, if enabled
+    inspector: Option<Arc<InspectorServer>>,
+    
+    /// Key/value store, secrets and metrics shared by all isolates
+    services: HostServices,
+    
+    /// Functions callable through `/api/functions/{name}`, with their schemas
+    functions: FunctionRegistry,
+    
+    /// Functions exported by the main module
+    exports: ExportedFunctions,
+    
+    /// Directories backing the Cache API, `localStorage` and `Deno.openKv()`
+    storage: AppStorage,
 }
 
 impl DenoRuntime {
-    /// Create a new Deno runtime service
+    /// Create a new Deno runtime service for the application at `DENO_APP_PATH`
     pub fn new(config: Arc<ServerConfig>) -> Self {
-        Self { config }
+        let inspector = config.deno_inspect.as_ref().map(|inspect| {
+            if !inspect.addr.ip().is_loopback() {
+                warn!("Deno inspector on {} is reachable from other hosts", inspect.addr);
+            }
+            Arc::new(InspectorServer::new(inspect.addr, "webserver"))
+        });
+        
+        let services = HostServices {
+            secrets: SecretStore::new(config.secrets.clone()),
+            ..Default::default()
+        };
+        
+        let main_module = config.deno_app_path.clone();
+        let snapshot = Self::snapshot_cache(&config, &main_module);
+        let storage = AppStorage::new(&config.data_dir, StorageScope::Main);
+        
+        Self {
+            config,
+            main_module,
+            module_root: None,
+            module_graph: ModuleGraph::default(),
+            snapshot,
+            inspector,
+            services,
+            functions: FunctionRegistry::default(),
+            exports: ExportedFunctions::default(),
+            storage,
+        }
+    }
+    
+    /// Create a runtime for another entry module that shares this one's inspector and services
+    ///
+    /// The module gets its own storage directories, named by `scope`.
+    pub fn for_module(&self, main_module: PathBuf, scope: StorageScope) -> Self {
+        Self {
+            config: self.config.clone(),
+            snapshot: Self::snapshot_cache(&self.config, &main_module),
+            main_module,
+            module_root: None,
+            module_graph: ModuleGraph::default(),
+            inspector: self.inspector.clone(),
+            services: self.services.clone(),
+            functions: FunctionRegistry::default(),
+            exports: ExportedFunctions::default(),
+            storage: AppStorage::new(&self.config.data_dir, scope),
+        }
+    }
+    
+    /// Create an isolated runtime for a deployed function bundle
+    ///
+    /// The bundle runs with its own permissions, limits and storage directories, and gets
+    /// its own key/value store and no secrets. Only the metrics registry is shared with
+    /// the application. Deno's unstable APIs are only enabled when the bundle asks for
+    /// `kv` and the server runs with `--deno-kv`. Modules are only loaded from `root`,
+    /// the directory of the bundle's version, and the vendor directory.
+    pub fn for_bundle(
+        &self,
+        name: &str,
+        root: PathBuf,
+        main_module: PathBuf,
+        permissions: DenoPermissions,
+        call_timeout: Option<Duration>,
+        max_heap_mb: Option<usize>,
+        kv: bool,
+    ) -> Self {
+        let mut config = (*self.config).clone();
+        config.secrets.clear();
+        config.deno_kv = config.deno_kv && kv;
+        config.deno_permissions = permissions;
+        config.deno_call_timeout = call_timeout;
+        config.deno_max_heap_mb = max_heap_mb;
+        config.deno_snapshot = false;
+        config.deno_inspect = None;
+        let storage = AppStorage::new(&config.data_dir, StorageScope::Bundle(name.to_string()));
+        
+        Self {
+            config: Arc::new(config),
+            main_module,
+            module_root: Some(root),
+            module_graph: ModuleGraph::default(),
+            snapshot: None,
+            inspector: None,
+            services: HostServices {
+                metrics: self.services.metrics.clone(),
+                ..Default::default()
+            },
+            functions: FunctionRegistry::default(),
+            exports: ExportedFunctions::default(),
+            storage,
+        }
+    }
+    
+    fn snapshot_cache(config: &ServerConfig, main_module: &Path) -> Option<SnapshotCache> {
+        // Snapshotted modules never reach the debugger, so snapshots are off while inspecting
+        (config.deno_snapshot && config.deno_inspect.is_none())
+            .then(|| SnapshotCache::new(&config.deno_cache_dir, main_module))
+    }
+    
+    /// Names of the secrets available to the application
+    pub fn secret_names(&self) -> Vec<&str> {
+        self.config.secrets.keys().map(String::as_str).collect()
+    }
+    
+    /// Services exposed to scripts through the `Webserver` API
+    pub fn services(&self) -> &HostServices {
+        &self.services
+    }
+    
+    /// Functions the application registered through its `schemas` export
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+    
+    /// Functions exported by the main module, as of the last isolate that loaded it
+    pub fn exports(&self) -> &ExportedFunctions {
+        &self.exports
+    }
+    
+    /// Storage directories of the application
+    pub fn storage(&self) -> &AppStorage {
+        &self.storage
+    }
+    
+    /// Files loaded as modules so far, for watching the application's import graph
+    pub fn module_graph(&self) -> &ModuleGraph {
+        &self.module_graph
     }
 
     /// Initialize the Deno runtime with the specified main module
     pub async fn init(&self) -> Result<MainWorker> {
-        let module_path = self.config.deno_app_path.clone();
+        let module_path = self.main_module.clone();
         let module_url = deno_core::resolve_url_or_path(&module_path.to_string_lossy())?;
         
-        let create_web_worker_cb = Arc::new(|_| {
-            todo!("Web workers are not supported in this implementation");
-        });
+        // Transpile TypeScript and map stack traces back to the original sources
+        let source_maps = SourceMapStore::default();
+        
+        // Serve npm:, jsr: and remote imports from the local vendor directory
+        let vendor = self.config.deno_vendor_dir.as_deref().map(Vendor::load).transpose()?;
+
+        // Boot from the startup snapshot when it matches the current sources
+        let startup_snapshot = self
+            .snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.load(&module_url, &self.module_graph));
+        let from_snapshot = startup_snapshot.is_some();
 
         let blob_store = BlobStore::default();
         let broadcast_channel = InMemoryBroadcastChannel::default();
+        
+        // Keep the Cache API, localStorage and Deno KV across restarts
+        self.storage.create()?;
 
-        // Set up permissions (no permissions for now, which is secure by default)
-        let permissions = Permissions::allow_all();
-
-        // Bootstrap options
-        let bootstrap_options = BootstrapOptions {
-            args: vec![],
-            cpu_count: num_cpus::get() as u32,
-            debug_flag: false,
-            enable_testing_features: false,
-            location: None,
-            no_color: false,
-            is_tty: false,
-            runtime_version: "v1.0.0".to_string(),
-            ts_version: "4.9.4".to_string(),
-            unstable: false,
-            user_agent: "deno/1.0.0".to_string(),
-            inspect: false,
-        };
+        // Log console output and stdout/stderr with the request and function being served
+        let current_call = CurrentCall::default();
+        let stdio = capture_stdio(&current_call)?;
+        DENIAL_CONTEXT.with(|context| *context.borrow_mut() = current_call.clone());
+
+        // Web workers share the main worker's loader configuration, blob store, limits and logging
+        let web_workers = Arc::new(WebWorkerFactory {
+            cache_dir: self.config.deno_cache_dir.clone(),
+            vendor: vendor.clone(),
+            module_graph: self.module_graph.clone(),
+            blob_store: blob_store.clone(),
+            broadcast_channel: broadcast_channel.clone(),
+            inspector: self.inspector.clone(),
+            cache_storage_dir: self.storage.cache_dir(),
+            unstable: self.config.deno_kv,
+            max_heap_mb: self.config.deno_max_heap_mb,
+            services: self.services.clone(),
+            current_call: current_call.clone(),
+            module_root: self.module_root.clone(),
+        });
+
+        // Set up the permission sandbox from configuration
+        let permissions = Permissions::from_options(&permissions_options(&self.config.deno_permissions))
+            .map_err(|e| ServerError::Config(format!("Invalid Deno permissions: {}", e)))?;
+        let permissions = PermissionsContainer::new(permissions);
 
         // Worker options
         let options = WorkerOptions {
-            bootstrap: bootstrap_options,
-            extensions: vec![],
+            bootstrap: bootstrap_options(self.inspector.is_some(), self.config.deno_kv),
+            extensions: vec![webserver_ops::init_ops(self.services.clone())],
             unsafely_ignore_certificate_errors: None,
             root_cert_store: None,
             seed: None,
-            source_map_getter: None,
+            source_map_getter: Some(Box::new(source_maps.clone())),
             format_js_error_fn: None,
-            web_worker_preload_module_cb: create_web_worker_cb.clone(),
-            web_worker_pre_execute_module_cb: create_web_worker_cb,
-            create_web_worker_cb: Arc::new(|_| {
-                todo!("Web workers are not supported in this implementation");
-            }),
-            maybe_inspector_server: None,
-            should_break_on_first_statement: false,
-            should_wait_for_inspector_session: false,
-            module_loader: Rc::new(deno_runtime::deno_fs::FsModuleLoader),
+            web_worker_preload_module_cb: web_worker_event_cb(),
+            web_worker_pre_execute_module_cb: web_worker_event_cb(),
+            create_web_worker_cb: web_workers.create_cb(),
+            maybe_inspector_server: self.inspector.clone(),
+            should_break_on_first_statement: self.config.deno_inspect.as_ref().is_some_and(|inspect| inspect.break_on_start),
+            should_wait_for_inspector_session: self.config.deno_inspect.as_ref().is_some_and(|inspect| inspect.wait_for_session),
+            module_loader: Rc::new(
+                TsModuleLoader::new(self.config.deno_cache_dir.clone(), source_maps, vendor, self.module_graph.clone())
+                    .with_permissions(permissions.clone())
+                    .confined_to(self.module_root.clone()),
+            ),
             npm_resolver: None,
-            get_error_class_fn: None,
-            cache_storage_dir: None,
-            origin_storage_dir: None,
+            get_error_class_fn: Some(&error_class),
+            cache_storage_dir: Some(self.storage.cache_dir()),
+            origin_storage_dir: Some(self.storage.origin_dir()),
             blob_store: blob_store.clone(),
             broadcast_channel: broadcast_channel.clone(),
             shared_array_buffer_store: None,
             compiled_wasm_module_store: None,
-            stdio: Default::default(),
+            stdio,
+            create_params: heap_limits(self.config.deno_max_heap_mb),
+            startup_snapshot,
         };
 
         // Create main worker
         let mut worker = MainWorker::bootstrap_from_options(module_url.clone(), permissions, options);
         
-        // Set environment variables for the Deno runtime
-        self.set_environment_variables(&mut worker)?;
+        worker.js_runtime.op_state().borrow_mut().put(current_call);
+        
+        // Terminate scripts that approach the heap limit instead of aborting the process
+        self.install_heap_limit_callback(&mut worker);
+        
+        // Install the bridge used to call application functions
+        self.install_bridge(&mut worker, from_snapshot)?;
+        
+        // The snapshot already holds the evaluated main module
+        if from_snapshot {
+            self.register_functions(&mut worker)?;
+            self.register_exports(&mut worker)?;
+            return Ok(worker);
+        }
         
         // Execute the main module
-        worker.execute_main_module(&module_url).await?;
+        let deadline = CallDeadline::arm(&mut worker, self.config.deno_call_timeout);
+        let result = match worker.preload_main_module(&module_url).await {
+            Ok(id) => worker
+                .evaluate_module(id)
+                .await
+                .and_then(|()| expose_exports(&mut worker.js_runtime, id)),
+            Err(err) => Err(err),
+        };
+        if let Some(err) = termination_error(&mut worker, "main module", &deadline) {
+            return Err(err);
+        }
+        result.map_err(|e| map_js_error("main module", e))?;
+        self.register_functions(&mut worker)?;
+        self.register_exports(&mut worker)?;
+        
+        // Snapshot the sources that just loaded so the next boot can skip evaluation
+        if let Some(snapshot) = &self.snapshot {
+            match op_names(&mut worker.js_runtime) {
+                Ok(ops) => snapshot.rebuild_in_background(SnapshotSource {
+                    main_module: module_url,
+                    cache_dir: self.config.deno_cache_dir.clone(),
+                    vendor_dir: self.config.deno_vendor_dir.clone(),
+                    permissions: self.config.deno_permissions.clone(),
+                    unstable: self.config.deno_kv,
+                    services: self.services.clone(),
+                    ops,
+                }),
+                Err(err) => warn!("Not building a Deno startup snapshot: {}", err),
+            }
+        }
         
         Ok(worker)
     }
     
-    /// Set environment variables for the Deno runtime
-    fn set_environment_variables(&self, worker: &mut MainWorker) -> Result<()> {
-        // Set OpenAI API key
-        let script = format!(
-            r#"
-            Deno.env.set("OPENAI_API_KEY", "{}");
-            "#,
-            self.config.openai_api_key
-        );
+    /// Install a near-heap-limit callback that terminates the running script
+    fn install_heap_limit_callback(&self, worker: &mut MainWorker) {
+        if self.config.deno_max_heap_mb.is_none() {
+            return;
+        }
+        
+        let reached = terminate_near_heap_limit(&mut worker.js_runtime);
+        worker.js_runtime.op_state().borrow_mut().put(HeapLimitReached(reached));
+    }
+    
+    /// Install the call bridge used by `execute_function` and `execute_stream_function`, console
+    /// logging, and the `Webserver` API unless the startup snapshot already defines it
+    fn install_bridge(&self, worker: &mut MainWorker, from_snapshot: bool) -> Result<()> {
+        worker.execute_script("[webserver_bridge]", BRIDGE_SCRIPT)
+            .map_err(|e| ServerError::DenoExecution(format!("Failed to install call bridge: {}", e)))?;
+        
+        worker.execute_script("[webserver_console]", CONSOLE_SCRIPT)
+            .map_err(|e| ServerError::DenoExecution(format!("Failed to install console logging: {}", e)))?;
+        
+        if !from_snapshot {
+            worker.execute_script("[webserver_api]", WEBSERVER_API_SCRIPT)
+                .map_err(|e| ServerError::DenoExecution(format!("Failed to install Webserver API: {}", e)))?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Compile the JSON Schemas exported as `schemas` by the main module into the function registry
+    fn register_functions(&self, worker: &mut MainWorker) -> Result<()> {
+        let schemas = worker.execute_script("[webserver_functions]", FUNCTION_SCHEMAS_SCRIPT)
+            .map_err(|e| ServerError::DenoExecution(format!("Failed to read function schemas: {}", e)))?;
+        
+        let scope = &mut worker.js_runtime.handle_scope();
+        let local = v8::Local::new(scope, schemas);
+        let schemas: serde_json::Value = serde_v8::from_v8(scope, local)
+            .map_err(|e| ServerError::DenoV8(format!("Failed to convert function schemas: {}", e)))?;
         
-        worker.execute_script("[env_setup]", &script)
-            .map_err(|e| ServerError::DenoExecution(format!("Failed to set environment variables: {}", e)))?;
+        self.functions.load(schemas)
+    }
+    
+    /// Record the names of the functions exported by the main module
+    fn register_exports(&self, worker: &mut MainWorker) -> Result<()> {
+        let names = worker.execute_script("[webserver_exports]", EXPORT_NAMES_SCRIPT)
+            .map_err(|e| ServerError::DenoExecution(format!("Failed to list exports: {}", e)))?;
+        
+        let scope = &mut worker.js_runtime.handle_scope();
+        let local = v8::Local::new(scope, names);
+        let names: Vec<String> = serde_v8::from_v8(scope, local)
+            .map_err(|e| ServerError::DenoV8(format!("Failed to convert export names: {}", e)))?;
         
+        self.exports.replace(names);
         Ok(())
     }
     
     /// Execute a function in the Deno runtime
-    pub async fn execute_function(&self, worker: &mut MainWorker, function_name: &str, args: &str) -> Result<String> {
-        // Create a script to call the function with the provided arguments
-        let script = format!(
-            r#"
-            (async function() {{
-                if (typeof globalThis.{} !== "function") {{
-                    throw new Error("Function {} is not defined");
-                }}
-                
-                try {{
-                    const result = await globalThis.{}({});
-                    return result;
-                }} catch (error) {{
-                    throw new Error(`Error executing {}: ${{error.message}}`);
-                }}
-            }})();
-            "#,
-            function_name, function_name, function_name, args, function_name
-        );
-        
-        // Execute the script
-        let result = worker.execute_script("[execute_function]", &script)
-            .map_err(|e| ServerError::DenoExecution(format!("Failed to execute function: {}", e)))?;
-        
-        // Get the result from the promise
-        let global = worker.js_runtime.global_context();
+    ///
+    /// `args` is converted to a JavaScript value and passed as the single argument, and the
+    /// resolved result is deserialized into `R`. Functions that still return a JSON string
+    /// are parsed before deserialization.
+    pub async fn execute_function<A, R>(
+        &self,
+        worker: &mut MainWorker,
+        function_name: &str,
+        context: CallContext,
+        args: &A,
+    ) -> Result<R>
+    where
+        A: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        validate_function_name(function_name)?;
+        let args = serde_json::to_value(args)?;
+        
+        let timeout = self.config.deno_call_timeout;
+        let result = self.call_bridge(worker, "__webserverCall", function_name, context, args, timeout).await?;
+        
+        serde_json::from_value(result)
+            .map_err(|e| ServerError::DenoExecution(format!("Invalid result from {}: {}", function_name, e)))
+    }
+    
+    /// Execute a streaming function in the Deno runtime
+    ///
+    /// The function is called with the arguments and an `emit(chunk)` callback that sends
+    /// each chunk to `sink` as soon as it is produced. It may also return an async iterator
+    /// or an array of chunks, or a JSON string of such an array, which are emitted in order.
+    pub async fn execute_stream_function<A>(
+        &self,
+        worker: &mut MainWorker,
+        function_name: &str,
+        context: CallContext,
+        args: &A,
+        sink: mpsc::Sender<serde_json::Value>,
+    ) -> Result<()>
+    where
+        A: Serialize + ?Sized,
+    {
+        validate_function_name(function_name)?;
+        let args = serde_json::to_value(args)?;
+        
+        // Route emitted chunks to this call's sink for the duration of the call
+        worker.js_runtime.op_state().borrow_mut().put(StreamSink(Some(sink)));
+        let timeout = self.config.deno_stream_timeout;
+        let result = self.call_bridge(worker, "__webserverStream", function_name, context, args, timeout).await;
+        worker.js_runtime.op_state().borrow_mut().put(StreamSink(None));
+        
+        result.map(|_| ())
+    }
+    
+    /// Call a bridge function with the call context set, so output of the call is tagged with it
+    /// and the script can read it as `Webserver.context`
+    async fn call_bridge(
+        &self,
+        worker: &mut MainWorker,
+        bridge: &str,
+        function_name: &str,
+        context: CallContext,
+        args: serde_json::Value,
+        timeout: Option<Duration>,
+    ) -> Result<serde_json::Value> {
+        let current_call = worker.js_runtime.op_state().borrow().borrow::<CurrentCall>().clone();
+        current_call.set(context);
+        
+        let result = self.invoke_bridge(worker, bridge, function_name, args, timeout).await;
+        current_call.set(CallContext::default());
+        
+        result
+    }
+    
+    /// Call a bridge function with the target function name and arguments, and wait for the
+    /// result for at most `timeout`
+    async fn invoke_bridge(
+        &self,
+        worker: &mut MainWorker,
+        bridge: &str,
+        function_name: &str,
+        args: serde_json::Value,
+        timeout: Option<Duration>,
+    ) -> Result<serde_json::Value> {
+        let deadline = CallDeadline::arm(worker, timeout);
+        
+        let promise = {
+            let scope = &mut worker.js_runtime.handle_scope();
+            let context = scope.get_current_context();
+            let global = context.global(scope);
+            
+            // Look up the bridge function
+            let key = v8::String::new(scope, bridge)
+                .ok_or_else(|| ServerError::DenoV8("Failed to allocate function name".to_string()))?;
+            let value = global.get(scope, key.into())
+                .ok_or_else(|| ServerError::DenoV8(format!("Failed to read {}", bridge)))?;
+            let function = v8::Local::<v8::Function>::try_from(value)
+                .map_err(|_| ServerError::DenoRuntime(format!("Call bridge {} is not installed", bridge)))?;
+            
+            // Convert the arguments to JavaScript values
+            let name = v8::String::new(scope, function_name)
+                .ok_or_else(|| ServerError::DenoV8("Failed to allocate function name".to_string()))?;
+            let args = serde_v8::to_v8(scope, &args)
+                .map_err(|e| ServerError::DenoV8(format!("Failed to convert arguments: {}", e)))?;
+            
+            // Call the bridge, catching synchronous exceptions
+            let scope = &mut v8::TryCatch::new(scope);
+            let recv = v8::undefined(scope).into();
+            match function.call(scope, recv, &[name.into(), args]) {
+                Some(result) => Some(v8::Global::new(scope, result)),
+                None if scope.is_execution_terminating() => None,
+                None => {
+                    let message = scope
+                        .exception()
+                        .map(|exception| exception.to_rust_string_lossy(scope))
+                        .unwrap_or_else(|| "unknown exception".to_string());
+                    return Err(ServerError::DenoExecution(format!("Error executing {}: {}", function_name, message)));
+                }
+            }
+        };
+        
+        // Drive the event loop until the returned promise settles
+        let resolved = match promise {
+            Some(promise) => worker.js_runtime.resolve_value(promise).await,
+            None => Err(anyhow::anyhow!("execution terminated")),
+        };
+        
+        // Report termination by the deadline or heap limit ahead of the resulting JS error
+        if let Some(err) = termination_error(worker, function_name, &deadline) {
+            return Err(err);
+        }
+        let resolved = resolved.map_err(|e| map_js_error(function_name, e))?;
+        
         let scope = &mut worker.js_runtime.handle_scope();
-        let local = v8::Local::new(scope, result);
-        
-        // Resolve the promise
-        let promise = v8::Local::<v8::Promise>::try_from(local)
-            .map_err(|_| ServerError::DenoV8("Result is not a promise".to_string()))?;
-        
-        // Process microtasks to resolve the promise
-        worker.js_runtime.run_event_loop(false).await?;
-        
-        // Check promise state
-        match promise.state() {
-            v8::PromiseState::Fulfilled => {
-                let result = promise.result(scope);
-                let result_str = result.to_string(scope)
-                    .ok_or_else(|| ServerError::DenoV8("Failed to convert result to string".to_string()))?;
-                
-                let rust_string = result_str.to_rust_string_lossy(scope);
-                Ok(rust_string)
-            },
-            v8::PromiseState::Rejected => {
-                let result = promise.result(scope);
-                let result_str = result.to_string(scope)
-                    .ok_or_else(|| ServerError::DenoV8("Failed to convert error to string".to_string()))?;
-                
-                let error_message = result_str.to_rust_string_lossy(scope);
-                Err(ServerError::DenoExecution(error_message))
-            },
-            v8::PromiseState::Pending => {
-                Err(ServerError::DenoExecution("Promise is still pending".to_string()))
-            },
+        let local = v8::Local::new(scope, resolved);
+        serde_v8::from_v8(scope, local)
+            .map_err(|e| ServerError::DenoV8(format!("Failed to convert result of {}: {}", function_name, e)))
+    }
+}
+
+/// Set by the near-heap-limit callback once an isolate has been terminated for memory use
+struct HeapLimitReached(Arc<AtomicBool>);
+
+/// V8 parameters limiting the heap to `max_heap_mb`, if set
+///
+/// Configured and uploaded limits are validated to fit in bytes; anything larger is
+/// treated as the largest heap V8 can be given.
+fn heap_limits(max_heap_mb: Option<usize>) -> Option<v8::CreateParams> {
+    max_heap_mb.map(|mb| {
+        let bytes = mb.checked_mul(1024 * 1024).unwrap_or(usize::MAX);
+        v8::CreateParams::default().heap_limits(0, bytes)
+    })
+}
+
+/// Terminate the running script when the isolate nears its heap limit, instead of letting
+/// V8 abort the process, and return the flag set once that happened
+fn terminate_near_heap_limit(js_runtime: &mut JsRuntime) -> Arc<AtomicBool> {
+    let reached = Arc::new(AtomicBool::new(false));
+    let flag = reached.clone();
+    let handle = js_runtime.v8_isolate().thread_safe_handle();
+    
+    js_runtime.add_near_heap_limit_callback(move |current_limit, _initial_limit| {
+        flag.store(true, Ordering::SeqCst);
+        handle.terminate_execution();
+        // Give V8 room to unwind the terminated script
+        current_limit * 2
+    });
+    
+    reached
+}
+
+/// Terminates an isolate if a call is still running when its deadline passes
+///
+/// The timer runs on a blocking thread so it fires even when the script spins
+/// synchronously. `termination_error` disarms it once the call has returned; a timer
+/// that fired before that always has its termination reported. Dropping the deadline
+/// disarms it too and withdraws a termination still pending, so a timer firing on a
+/// call that returned early cannot kill the next call on the isolate.
+struct CallDeadline {
+    state: Arc<Mutex<DeadlineState>>,
+    handle: v8::IsolateHandle,
+    _cancel: Option<std_mpsc::Sender<()>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeadlineState {
+    Armed,
+    Disarmed,
+    /// The isolate has been told to terminate
+    Fired,
+}
+
+impl CallDeadline {
+    fn arm(worker: &mut MainWorker, timeout: Option<Duration>) -> Self {
+        let handle = worker.js_runtime.v8_isolate().thread_safe_handle();
+        let Some(timeout) = timeout else {
+            return Self { state: Arc::new(Mutex::new(DeadlineState::Disarmed)), handle, _cancel: None };
+        };
+        
+        let state = Arc::new(Mutex::new(DeadlineState::Armed));
+        let (cancel, cancelled) = std_mpsc::channel::<()>();
+        let timer_handle = handle.clone();
+        let timer_state = state.clone();
+        
+        tokio::task::spawn_blocking(move || {
+            if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(timeout) {
+                // Deciding and terminating under the lock keeps `disarm` from slipping in between
+                let mut state = timer_state.lock().unwrap();
+                if *state == DeadlineState::Armed {
+                    *state = DeadlineState::Fired;
+                    timer_handle.terminate_execution();
+                }
+            }
+        });
+        
+        Self { state, handle, _cancel: Some(cancel) }
+    }
+    
+    /// Stop the timer, returning whether it already terminated the isolate
+    fn disarm(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if *state == DeadlineState::Armed {
+            *state = DeadlineState::Disarmed;
+        }
+        *state == DeadlineState::Fired
+    }
+}
+
+impl Drop for CallDeadline {
+    fn drop(&mut self) {
+        if self.disarm() {
+            self.handle.cancel_terminate_execution();
+        }
+    }
+}
+
+/// Disarm the deadline and return the error for a call whose isolate was terminated, if it was
+///
+/// A deadline that fired counts even when the call finished first: the termination is still
+/// pending on the isolate, which must be rebuilt rather than handed the next call.
+fn termination_error(worker: &mut MainWorker, function_name: &str, deadline: &CallDeadline) -> Option<ServerError> {
+    let deadline_fired = deadline.disarm();
+    let heap_limit_reached = worker.js_runtime.op_state().borrow()
+        .try_borrow::<HeapLimitReached>()
+        .map(|reached| reached.0.load(Ordering::SeqCst))
+        .unwrap_or(false);
+    
+    if heap_limit_reached {
+        warn!(function = function_name, "Deno isolate terminated after reaching the heap limit");
+        Some(ServerError::DenoHeapLimit(function_name.to_string()))
+    } else if deadline_fired {
+        warn!(function = function_name, "Deno isolate terminated after the call deadline passed");
+        Some(ServerError::DenoTimeout(function_name.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Bootstrap options shared by the main worker, its web workers and snapshot builds
+pub(crate) fn bootstrap_options(inspect: bool, unstable: bool) -> BootstrapOptions {
+    BootstrapOptions {
+        args: vec![],
+        cpu_count: num_cpus::get() as u32,
+        debug_flag: false,
+        enable_testing_features: false,
+        location: None,
+        no_color: false,
+        is_tty: false,
+        runtime_version: "v1.0.0".to_string(),
+        ts_version: "4.9.4".to_string(),
+        unstable,
+        user_agent: "deno/1.0.0".to_string(),
+        inspect,
+    }
+}
+
+/// Creates web workers for `new Worker(...)` in the application
+///
+/// Each web worker runs on its own thread with its own isolate, so only thread-safe
+/// state is kept here; the module loader is rebuilt per worker from the same cache
+/// directory, vendor directory and module graph as the main worker. Web workers get
+/// the main worker's heap limit, and their console output and stdout/stderr are logged
+/// with the call the main worker is serving.
+struct WebWorkerFactory {
+    cache_dir: PathBuf,
+    vendor: Option<Vendor>,
+    module_graph: ModuleGraph,
+    blob_store: BlobStore,
+    broadcast_channel: InMemoryBroadcastChannel,
+    inspector: Option<Arc<InspectorServer>>,
+    cache_storage_dir: PathBuf,
+    unstable: bool,
+    max_heap_mb: Option<usize>,
+    services: HostServices,
+    current_call: CurrentCall,
+    module_root: Option<PathBuf>,
+}
+
+impl WebWorkerFactory {
+    /// Callback handed to the runtime, also used for workers created by web workers
+    fn create_cb(self: &Arc<Self>) -> Arc<CreateWebWorkerCb> {
+        let factory = self.clone();
+        Arc::new(move |args| factory.create(args))
+    }
+    
+    /// Runs on the new web worker's thread
+    fn create(self: &Arc<Self>, args: CreateWebWorkerArgs) -> (WebWorker, SendableWebWorkerHandle) {
+        let source_maps = SourceMapStore::default();
+        
+        // The callback cannot fail, so a worker whose output cannot be captured inherits the server's
+        DENIAL_CONTEXT.with(|context| *context.borrow_mut() = self.current_call.clone());
+        let stdio = capture_stdio(&self.current_call).unwrap_or_else(|err| {
+            warn!("Failed to capture the output of Deno web worker {:?}: {}", args.name, err);
+            Stdio::default()
+        });
+        
+        let options = WebWorkerOptions {
+            bootstrap: bootstrap_options(self.inspector.is_some(), self.unstable),
+            extensions: vec![webserver_ops::init_ops(self.services.clone())],
+            unsafely_ignore_certificate_errors: None,
+            root_cert_store: None,
+            seed: None,
+            module_loader: Rc::new(
+                TsModuleLoader::new(self.cache_dir.clone(), source_maps.clone(), self.vendor.clone(), self.module_graph.clone())
+                    .with_permissions(args.permissions.clone())
+                    .confined_to(self.module_root.clone()),
+            ),
+            npm_resolver: None,
+            create_web_worker_cb: self.create_cb(),
+            preload_module_cb: web_worker_event_cb(),
+            pre_execute_module_cb: web_worker_event_cb(),
+            format_js_error_fn: None,
+            source_map_getter: Some(Box::new(source_maps)),
+            worker_type: args.worker_type,
+            maybe_inspector_server: self.inspector.clone(),
+            get_error_class_fn: Some(&error_class),
+            blob_store: self.blob_store.clone(),
+            broadcast_channel: self.broadcast_channel.clone(),
+            shared_array_buffer_store: None,
+            compiled_wasm_module_store: None,
+            cache_storage_dir: Some(self.cache_storage_dir.clone()),
+            stdio,
+            create_params: heap_limits(self.max_heap_mb),
+        };
+        
+        // `args.permissions` is the parent's permissions, narrowed by any `deno.permissions` worker option
+        let name = args.name.clone();
+        let (mut worker, handle) =
+            WebWorker::bootstrap_from_options(args.name, args.permissions, args.main_module, args.worker_id, options);
+        
+        worker.js_runtime.op_state().borrow_mut().put(self.current_call.clone());
+        if self.max_heap_mb.is_some() {
+            terminate_near_heap_limit(&mut worker.js_runtime);
+        }
+        if let Err(err) = worker.execute_script("[webserver_console]", CONSOLE_SCRIPT) {
+            warn!("Failed to install console logging in Deno web worker {:?}: {}", name, err);
         }
+        
+        (worker, handle)
     }
-}
\ No newline at end of file
+}
+
+/// Pipe the isolate's stdout and stderr into the log, one event per line
+fn capture_stdio(current_call: &CurrentCall) -> Result<Stdio> {
+    Ok(Stdio {
+        stdin: StdioPipe::Inherit,
+        stdout: StdioPipe::File(forward_output("stdout", "info", current_call.clone())?),
+        stderr: StdioPipe::File(forward_output("stderr", "warn", current_call.clone())?),
+    })
+}
+
+/// Create a pipe whose lines are logged at `level` by a background thread
+///
+/// The thread exits once the isolate drops the write end.
+fn forward_output(stream: &str, level: &'static str, current_call: CurrentCall) -> Result<std::fs::File> {
+    let (reader, writer) = os_pipe::pipe()?;
+    
+    thread::Builder::new()
+        .name(format!("deno-{}", stream))
+        .spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                log_output(level, &line, None, &current_call.get());
+            }
+        })?;
+    
+    Ok(pipe_file(writer))
+}
+
+#[cfg(unix)]
+fn pipe_file(writer: os_pipe::PipeWriter) -> std::fs::File {
+    std::fs::File::from(std::os::fd::OwnedFd::from(writer))
+}
+
+#[cfg(windows)]
+fn pipe_file(writer: os_pipe::PipeWriter) -> std::fs::File {
+    std::fs::File::from(std::os::windows::io::OwnedHandle::from(writer))
+}
+
+/// Worker event callback that leaves the web worker unchanged
+fn web_worker_event_cb() -> Arc<WorkerEventCb> {
+    Arc::new(|worker| LocalFutureObj::new(Box::new(futures::future::ready(Ok(worker)))))
+}
+
+/// Map the configured permissions onto Deno's permission options
+pub(crate) fn permissions_options(permissions: &DenoPermissions) -> PermissionsOptions {
+    PermissionsOptions {
+        allow_read: permissions.allow_read.clone(),
+        allow_write: permissions.allow_write.clone(),
+        allow_env: permissions.allow_env.clone(),
+        allow_net: permissions.allow_net.clone(),
+        allow_run: permissions.allow_run.clone(),
+        allow_ffi: permissions.allow_ffi.clone(),
+        prompt: false,
+        ..Default::default()
+    }
+}
+
+thread_local! {
+    /// Call being served by the isolate running on this thread, for logging permission denials
+    static DENIAL_CONTEXT: RefCell<CurrentCall> = RefCell::new(CurrentCall::default());
+}
+
+/// Error class of an error raised by an op, logging permission denials as the sandbox refuses them
+///
+/// Logging at the check rather than when the call fails keeps a record of denials the
+/// script catches.
+fn error_class(err: &AnyError) -> &'static str {
+    let class = deno_runtime::errors::get_error_class_name(err).unwrap_or("Error");
+    if class == "PermissionDenied" {
+        let context = DENIAL_CONTEXT.with(|context| context.borrow().get());
+        log_output("warn", &format!("Deno permission denied: {}", err), None, &context);
+    }
+    class
+}
+
+/// Convert an error thrown by JavaScript into a `ServerError`
+///
+/// Denials were already logged by `error_class` when the permission check failed.
+fn map_js_error(function_name: &str, err: AnyError) -> ServerError {
+    let denied = err
+        .downcast_ref::<JsError>()
+        .map(|js_error| {
+            js_error.name.as_deref() == Some("PermissionDenied")
+                || js_error.exception_message.contains("PermissionDenied")
+        })
+        .unwrap_or_else(|| deno_core::error::get_custom_error_class(&err) == Some("PermissionDenied"));
+    
+    if denied {
+        ServerError::DenoPermission(format!("{}: {}", function_name, err))
+    } else {
+        ServerError::DenoExecution(format!("Error executing {}: {}", function_name, err))
+    }
+}
+
+/// Check that a function name is a plain JavaScript identifier before it reaches the isolate
+fn validate_function_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+    let valid_start = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$');
+    let valid_rest = chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    
+    if !valid_start || !valid_rest || name.starts_with("__webserver") {
+        return Err(ServerError::DenoExecution(format!("Invalid function name: {:?}", name)));
+    }
+    
+    Ok(())
+}
+
+/// Make the main module's exports callable through the bridge
+///
+/// The namespace is stored on a hidden global, so it also survives in startup snapshots.
+pub(crate) fn expose_exports(js_runtime: &mut JsRuntime, module_id: ModuleId) -> std::result::Result<(), AnyError> {
+    let namespace = js_runtime.get_module_namespace(module_id)?;
+    let scope = &mut js_runtime.handle_scope();
+    let global = scope.get_current_context().global(scope);
+    
+    let key = v8::String::new(scope, "__webserverExports")
+        .ok_or_else(|| anyhow::anyhow!("Failed to allocate export key"))?;
+    let namespace = v8::Local::new(scope, namespace);
+    global.define_own_property(scope, key.into(), namespace.into(), v8::PropertyAttribute::DONT_ENUM);
+    
+    Ok(())
+}
+
+/// Names of the ops registered with a runtime, in the order their ids were assigned
+pub(crate) fn op_names(js_runtime: &mut JsRuntime) -> Result<Vec<String>> {
+    let names = js_runtime.execute_script_static("[webserver_ops]", OP_NAMES_SCRIPT)
+        .map_err(|e| ServerError::DenoExecution(format!("Failed to list ops: {}", e)))?;
+    
+    let scope = &mut js_runtime.handle_scope();
+    let local = v8::Local::new(scope, names);
+    serde_v8::from_v8(scope, local)
+        .map_err(|e| ServerError::DenoV8(format!("Failed to convert op names: {}", e)))
+}
+
+/// Defines `globalThis.Webserver`, typed by `types/webserver.d.ts`
+pub(crate) const WEBSERVER_API_SCRIPT: &str = include_str!("js/webserver.js");
+
+/// Lists the ops registered with the isolate, in registration order
+const OP_NAMES_SCRIPT: &str = "Object.keys(Deno.core.ops)";
+
+/// Sends console output to the server log, tagged with the request being served
+///
+/// Installed in the main isolate and in every web worker.
+const CONSOLE_SCRIPT: &str = r#"
+(() => {
+    const format = (args) => args.map((arg) => (typeof arg === "string" ? arg : Deno.inspect(arg))).join(" ");
+    const levels = { debug: "debug", trace: "trace", log: "info", info: "info", dir: "info", warn: "warn", error: "error" };
+    for (const [method, level] of Object.entries(levels)) {
+        console[method] = (...args) => Deno.core.ops.op_log(level, format(args));
+    }
+})();
+"#;
+
+/// Lists the names of the functions the main module exports
+const EXPORT_NAMES_SCRIPT: &str = r#"
+Object.entries(globalThis.__webserverExports ?? {})
+    .filter(([, value]) => typeof value === "function")
+    .map(([name]) => name)
+"#;
+
+/// Reads the JSON Schemas of the functions the main module registers for `/api/functions`
+const FUNCTION_SCHEMAS_SCRIPT: &str = "globalThis.__webserverExports?.schemas ?? null";
+
+/// Bridge functions that look up and call application functions by name
+///
+/// Arguments and results cross the boundary as V8 values, so no user data is ever
+/// interpolated into script source.
+const BRIDGE_SCRIPT: &str = r#"
+(() => {
+    // Exports of the main module take precedence over globals of the same name
+    const lookup = (name) => {
+        const fn = globalThis.__webserverExports?.[name] ?? globalThis[name];
+        if (typeof fn !== "function") {
+            throw new Error(`Function ${name} is not defined`);
+        }
+        return fn;
+    };
+    
+    const parseResult = (result) => {
+        if (typeof result === "string") {
+            try {
+                return JSON.parse(result);
+            } catch {
+                return result;
+            }
+        }
+        return result ?? null;
+    };
+    
+    const emit = (chunk) => Deno.core.opAsync("op_stream_chunk", chunk);
+    
+    Object.defineProperty(globalThis, "__webserverCall", {
+        value: async (name, args) => parseResult(await lookup(name)(args)),
+    });
+    
+    Object.defineProperty(globalThis, "__webserverStream", {
+        value: async (name, args) => {
+            const result = parseResult(await lookup(name)(args, emit));
+            if (result != null && (Array.isArray(result) || typeof result[Symbol.asyncIterator] === "function")) {
+                for await (const chunk of result) {
+                    if (!(await emit(chunk))) {
+                        break;
+                    }
+                }
+            }
+            return null;
+        },
+    });
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_names_must_be_plain_identifiers() {
+        for name in ["handleCopilotRequest", "_private", "$handler", "handler2", "snake_case", "a"] {
+            assert!(validate_function_name(name).is_ok(), "{} should be accepted", name);
+        }
+
+        for name in [
+            "",
+            "2handler",
+            "handler-name",
+            "handler.call",
+            "globalThis[\"handler\"]",
+            "handler()",
+            "handler name",
+            "handlér",
+            "__webserverCall",
+            "__webserverExports",
+        ] {
+            assert!(
+                matches!(validate_function_name(name), Err(ServerError::DenoExecution(_))),
+                "{:?} should be rejected",
+                name
+            );
+        }
+    }
+}
@@ -24,6 +24,26 @@ pub enum ServerError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    /// Secrets provider errors, which never include secret values
+    #[error("Secrets error: {0}")]
+    Secret(String),
+
+    /// A deployed function bundle or version that does not exist
+    #[error("Bundle not found: {0}")]
+    BundleNotFound(String),
+
+    /// A function bundle that was rejected or failed to load
+    #[error("Invalid bundle: {0}")]
+    InvalidBundle(String),
+
+    /// Certificates or keys that cannot be loaded for HTTPS
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    /// Failures of the AI backend answering copilot requests
+    #[error("AI backend error: {0}")]
+    Backend(String),
+
     /// General server errors
     #[error("Server error: {0}")]
     Server(String),
@@ -36,6 +56,26 @@ pub enum ServerError {
     #[error("Deno execution error: {0}")]
     DenoExecution(String),
     
+    /// Deno permission sandbox denials
+    #[error("Deno permission denied: {0}")]
+    DenoPermission(String),
+    
+    /// Deno calls terminated for exceeding the execution deadline
+    #[error("Deno execution timed out: {0}")]
+    DenoTimeout(String),
+    
+    /// Deno calls terminated for exceeding the V8 heap limit
+    #[error("Deno heap limit exceeded: {0}")]
+    DenoHeapLimit(String),
+    
+    /// No Deno worker is able to serve the call
+    #[error("Deno runtime unavailable: {0}")]
+    DenoUnavailable(String),
+    
+    /// A Rust panic while a Deno worker was executing a call
+    #[error("Deno worker panicked while executing {0}")]
+    DenoPanic(String),
+    
     /// Deno V8 errors
     #[error("Deno V8 error: {0}")]
     DenoV8(String),
@@ -45,6 +85,14 @@ pub enum ServerError {
     EnvVar(String),
 }
 
+impl ServerError {
+    /// Whether the error left the Deno isolate terminated, so the worker must be rebuilt
+    pub fn is_isolate_terminated(&self) -> bool {
+        matches!(self, ServerError::DenoTimeout(_) | ServerError::DenoHeapLimit(_))
+    }
+}
+
+#[cfg(feature = "deno")]
 impl From<deno_core::error::AnyError> for ServerError {
     fn from(err: deno_core::error::AnyError) -> Self {
         ServerError::DenoRuntime(err.to_string())
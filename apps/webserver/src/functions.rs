@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use jsonschema::JSONSchema;
+use serde::Serialize;
+
+use crate::error::{Result, ServerError};
+
+/// JSON Schemas of a function callable through `/api/functions/{name}`
+pub struct FunctionSpec {
+    input: Option<JSONSchema>,
+    output: Option<JSONSchema>,
+}
+
+/// A place where a value does not match its schema
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the offending value, empty for the root
+    pub path: String,
+    pub message: String,
+}
+
+impl FunctionSpec {
+    /// Check a function's arguments against its input schema
+    pub fn validate_input(&self, input: &serde_json::Value) -> std::result::Result<(), Vec<SchemaViolation>> {
+        validate(self.input.as_ref(), input)
+    }
+
+    /// Check a function's result against its output schema
+    pub fn validate_output(&self, output: &serde_json::Value) -> std::result::Result<(), Vec<SchemaViolation>> {
+        validate(self.output.as_ref(), output)
+    }
+}
+
+/// Functions the Deno application registered by exporting `schemas`
+///
+/// Replaced every time an isolate loads the application, so it follows reloads.
+#[derive(Clone, Default)]
+pub struct FunctionRegistry(Arc<RwLock<HashMap<String, Arc<FunctionSpec>>>>);
+
+impl FunctionRegistry {
+    /// Look up a registered function
+    pub fn get(&self, name: &str) -> Option<Arc<FunctionSpec>> {
+        self.0.read().unwrap().get(name).cloned()
+    }
+
+    /// Names of all registered functions, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.0.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Replace the registry with the `schemas` export of a freshly loaded application
+    ///
+    /// `schemas` maps function names to `{ input?, output? }` JSON Schemas; `null`
+    /// means the application registers no functions.
+    pub fn load(&self, schemas: serde_json::Value) -> Result<()> {
+        let entries = match schemas {
+            serde_json::Value::Null => serde_json::Map::new(),
+            serde_json::Value::Object(entries) => entries,
+            _ => return Err(ServerError::DenoExecution("Exported `schemas` must be an object".to_string())),
+        };
+
+        let mut specs = HashMap::with_capacity(entries.len());
+        for (name, entry) in entries {
+            let spec = FunctionSpec {
+                input: compile(&name, "input", entry.get("input"))?,
+                output: compile(&name, "output", entry.get("output"))?,
+            };
+            specs.insert(name, Arc::new(spec));
+        }
+
+        *self.0.write().unwrap() = specs;
+        Ok(())
+    }
+}
+
+/// Names of the functions exported by a module, the only functions bundle calls may reach
+///
+/// Like `FunctionRegistry`, it is replaced every time an isolate loads the module. Globals
+/// such as `close` or `prompt` are never listed, so they cannot be called by name.
+#[derive(Clone, Default)]
+pub struct ExportedFunctions(Arc<RwLock<HashSet<String>>>);
+
+impl ExportedFunctions {
+    /// Whether the module exports a function called `name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.read().unwrap().contains(name)
+    }
+
+    /// Replace the names with those of a freshly loaded module
+    pub fn replace(&self, names: Vec<String>) {
+        *self.0.write().unwrap() = names.into_iter().collect();
+    }
+}
+
+fn compile(function: &str, kind: &str, schema: Option<&serde_json::Value>) -> Result<Option<JSONSchema>> {
+    let Some(schema) = schema.filter(|schema| !schema.is_null()) else {
+        return Ok(None);
+    };
+
+    JSONSchema::compile(schema)
+        .map(Some)
+        .map_err(|e| ServerError::DenoExecution(format!("Invalid {} schema for {}: {}", kind, function, e)))
+}
+
+fn validate(schema: Option<&JSONSchema>, value: &serde_json::Value) -> std::result::Result<(), Vec<SchemaViolation>> {
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+
+    schema.validate(value).map_err(|errors| {
+        errors
+            .map(|error| SchemaViolation {
+                path: error.instance_path.to_string(),
+                message: error.to_string(),
+            })
+            .collect()
+    })
+}
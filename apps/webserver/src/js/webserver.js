@@ -0,0 +1,33 @@
+// Server-side facilities for the Deno application, declared in types/webserver.d.ts
+(() => {
+    const ops = Deno.core.ops;
+    
+    const log = (level) => (message, fields) => ops.op_log(level, String(message), fields ?? null);
+    
+    const Webserver = Object.freeze({
+        log: Object.freeze({
+            trace: log("trace"),
+            debug: log("debug"),
+            info: log("info"),
+            warn: log("warn"),
+            error: log("error"),
+        }),
+        kv: Object.freeze({
+            get: (key) => ops.op_kv_get(key) ?? undefined,
+            set: (key, value) => ops.op_kv_set(key, value),
+            delete: (key) => ops.op_kv_delete(key),
+            list: (prefix = "") => ops.op_kv_list(prefix),
+        }),
+        secrets: Object.freeze({
+            get: (name) => ops.op_secret_get(name) ?? undefined,
+        }),
+        metrics: Object.freeze({
+            increment: (name, by = 1) => ops.op_metric_increment(name, by),
+        }),
+        get context() {
+            return ops.op_call_context();
+        },
+    });
+    
+    Object.defineProperty(globalThis, "Webserver", { value: Webserver, enumerable: false });
+})();
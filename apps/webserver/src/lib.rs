@@ -1,17 +1,55 @@
+pub mod admin;
+#[cfg(feature = "deno")]
+pub mod apps;
+pub mod backend;
+#[cfg(feature = "deno")]
+pub mod bundles;
+pub mod client_identity;
 pub mod config;
 pub mod error;
 pub mod server;
+#[cfg(feature = "deno")]
+pub mod vendor;
+#[cfg(feature = "deno")]
+pub mod watch;
+#[cfg(feature = "deno")]
 pub mod deno;
+#[cfg(feature = "deno")]
+pub mod functions;
+pub mod limits;
+#[cfg(feature = "deno")]
+pub mod loader;
+#[cfg(feature = "deno")]
+pub mod ops;
+#[cfg(feature = "deno")]
+pub mod pool;
+pub mod reload;
+pub mod request_id;
+pub mod routes;
+pub mod secrets;
+pub mod settings;
+pub mod shutdown;
+#[cfg(feature = "deno")]
+pub mod snapshot;
+pub mod storage;
+pub mod tls;
 
 pub use config::ServerConfig;
 pub use error::{ServerError, Result};
 pub use server::Server;
+#[cfg(feature = "deno")]
 pub use deno::DenoRuntime;
+#[cfg(feature = "deno")]
+pub use pool::DenoPool;
 
 /// Re-export important types for convenience
 pub mod prelude {
     pub use crate::config::ServerConfig;
     pub use crate::error::{ServerError, Result};
     pub use crate::server::Server;
+    pub use crate::backend::AiBackend;
+    #[cfg(feature = "deno")]
     pub use crate::deno::DenoRuntime;
+    #[cfg(feature = "deno")]
+    pub use crate::pool::DenoPool;
 }
\ No newline at end of file
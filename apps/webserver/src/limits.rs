@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::error::PayloadError;
+use futures::StreamExt;
+
+/// Path prefix of the admin API, whose bundle uploads have their own limit
+const ADMIN_PREFIX: &str = "/api/admin/";
+
+/// Request body limits, which can be changed while the server runs
+///
+/// Extractors are configured without a limit of their own; every request body is cut
+/// off by `limit_body` instead, so a reload applies to the next request.
+#[derive(Clone)]
+pub struct Limits(Arc<LimitValues>);
+
+struct LimitValues {
+    max_json_bytes: AtomicUsize,
+    max_upload_bytes: AtomicUsize,
+}
+
+impl Limits {
+    /// Accept API bodies up to `max_json_bytes` and admin uploads up to `max_upload_bytes`
+    pub fn new(max_json_bytes: usize, max_upload_bytes: usize) -> Self {
+        Self(Arc::new(LimitValues {
+            max_json_bytes: AtomicUsize::new(max_json_bytes),
+            max_upload_bytes: AtomicUsize::new(max_upload_bytes),
+        }))
+    }
+
+    /// Replace both limits
+    pub fn set(&self, max_json_bytes: usize, max_upload_bytes: usize) {
+        self.0.max_json_bytes.store(max_json_bytes, Ordering::Relaxed);
+        self.0.max_upload_bytes.store(max_upload_bytes, Ordering::Relaxed);
+    }
+
+    /// Largest body accepted for a request to `path`, in bytes
+    pub fn for_path(&self, path: &str) -> usize {
+        if path.starts_with(ADMIN_PREFIX) {
+            self.0.max_upload_bytes.load(Ordering::Relaxed)
+        } else {
+            self.0.max_json_bytes.load(Ordering::Relaxed)
+        }
+    }
+
+    /// Fail the body of `req` with `413 Payload Too Large` once it exceeds the limit for its path
+    pub fn limit_body(&self, req: ServiceRequest) -> ServiceRequest {
+        let limit = self.for_path(req.path());
+        let (http_req, payload) = req.into_parts();
+
+        let mut received = 0;
+        let limited = payload.map(move |chunk| {
+            let chunk = chunk?;
+            received += chunk.len();
+            if received > limit {
+                return Err(PayloadError::Overflow);
+            }
+            Ok(chunk)
+        });
+
+        ServiceRequest::from_parts(http_req, Payload::Stream { payload: Box::pin(limited) })
+    }
+}
@@ -0,0 +1,356 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use deno_ast::{EmitOptions, MediaType, ParseParams, SourceTextInfo};
+use deno_core::error::{custom_error, generic_error, AnyError};
+use deno_core::{
+    ModuleLoader, ModuleSource, ModuleSourceFuture, ModuleSpecifier, ModuleType, ResolutionKind,
+    SourceMapGetter,
+};
+use deno_runtime::permissions::PermissionsContainer;
+use futures::FutureExt;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use crate::vendor::Vendor;
+
+/// Bumped whenever the emit options change so stale cache entries are ignored
+const EMIT_CACHE_VERSION: &str = "1";
+
+/// Original sources and source maps of the modules loaded into an isolate
+#[derive(Default)]
+struct LoadedModule {
+    source: String,
+    source_map: Option<Vec<u8>>,
+}
+
+/// Source maps shared between the module loader and the runtime's error formatter
+#[derive(Clone, Default)]
+pub struct SourceMapStore(Rc<RefCell<HashMap<String, LoadedModule>>>);
+
+impl SourceMapGetter for SourceMapStore {
+    fn get_source_map(&self, file_name: &str) -> Option<Vec<u8>> {
+        self.0.borrow().get(file_name)?.source_map.clone()
+    }
+
+    fn get_source_line(&self, file_name: &str, line_number: usize) -> Option<String> {
+        let modules = self.0.borrow();
+        let line = modules.get(file_name)?.source.lines().nth(line_number)?;
+        Some(line.to_string())
+    }
+}
+
+/// Local files loaded by any isolate, used to watch the application's import graph
+#[derive(Clone, Default)]
+pub struct ModuleGraph(Arc<Mutex<BTreeSet<PathBuf>>>);
+
+impl ModuleGraph {
+    /// Record a file that was loaded as a module
+    pub fn insert(&self, path: PathBuf) {
+        self.0.lock().unwrap().insert(path);
+    }
+
+    /// Whether a file is part of the graph
+    pub fn contains(&self, path: &Path) -> bool {
+        self.0.lock().unwrap().contains(path)
+    }
+
+    /// All files loaded so far
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Module loader that transpiles TypeScript and JSX from the local file system
+///
+/// Emitted JavaScript and its source map are cached on disk, keyed by a hash of the
+/// specifier and the original source, so unchanged modules are only transpiled once
+/// across workers and restarts.
+///
+/// `npm:`, `jsr:` and remote imports are resolved against the vendor directory, if
+/// one is configured, and rejected otherwise.
+///
+/// Dynamic imports of local files need read permission, like `Deno.readFile`. A loader
+/// confined to a root, as for function bundles, only loads files inside it.
+pub struct TsModuleLoader {
+    cache_dir: PathBuf,
+    source_maps: SourceMapStore,
+    vendor: Option<Vendor>,
+    graph: ModuleGraph,
+    permissions: Option<PermissionsContainer>,
+    root: Option<PathBuf>,
+}
+
+impl TsModuleLoader {
+    /// Create a loader that caches emitted JavaScript in `cache_dir` and records loaded files in `graph`
+    pub fn new(cache_dir: PathBuf, source_maps: SourceMapStore, vendor: Option<Vendor>, graph: ModuleGraph) -> Self {
+        Self {
+            cache_dir,
+            source_maps,
+            vendor,
+            graph,
+            permissions: None,
+            root: None,
+        }
+    }
+
+    /// Check dynamic imports of local files against the isolate's read permission
+    pub fn with_permissions(mut self, permissions: PermissionsContainer) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Only load files inside `root`, besides vendored modules
+    ///
+    /// Files inside the root may also be imported dynamically without read permission,
+    /// since they could be imported statically anyway.
+    pub fn confined_to(mut self, root: Option<PathBuf>) -> Self {
+        self.root = root.map(|root| root.canonicalize().unwrap_or(root));
+        self
+    }
+
+    /// Read a module from disk, transpiling it if needed
+    fn load_module(&self, specifier: &ModuleSpecifier, is_dyn_import: bool) -> Result<ModuleSource, AnyError> {
+        let path = specifier
+            .to_file_path()
+            .map_err(|_| generic_error(format!("Only file: modules can be loaded, got {}", specifier)))?;
+        self.check_access(specifier, &path, is_dyn_import)?;
+        let media_type = MediaType::from_specifier(specifier);
+
+        let source = fs::read_to_string(&path)
+            .map_err(|e| generic_error(format!("Failed to read module {}: {}", path.display(), e)))?;
+        self.graph.insert(path.canonicalize().unwrap_or(path));
+
+        let (module_type, code, source_map) = match media_type {
+            MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs | MediaType::Unknown => {
+                (ModuleType::JavaScript, source.clone(), None)
+            }
+            MediaType::Json => (ModuleType::Json, source.clone(), None),
+            MediaType::TypeScript
+            | MediaType::Mts
+            | MediaType::Cts
+            | MediaType::Jsx
+            | MediaType::Tsx => {
+                let (code, source_map) = self.emit(specifier, media_type, &source)?;
+                (ModuleType::JavaScript, code, source_map)
+            }
+            MediaType::Dts | MediaType::Dmts | MediaType::Dcts => (ModuleType::JavaScript, String::new(), None),
+            other => {
+                return Err(generic_error(format!("Unsupported module type {} for {}", other, specifier)));
+            }
+        };
+
+        self.source_maps.0.borrow_mut().insert(
+            specifier.to_string(),
+            LoadedModule {
+                source,
+                source_map: source_map.map(String::into_bytes),
+            },
+        );
+
+        Ok(ModuleSource::new(module_type, code.into(), specifier))
+    }
+
+    /// Keep confined loaders inside their root and check dynamic imports against the read permission
+    ///
+    /// Vendored files stand for the remote modules pinned by the vendor lockfile, so they
+    /// are always allowed.
+    fn check_access(&self, specifier: &ModuleSpecifier, path: &Path, is_dyn_import: bool) -> Result<(), AnyError> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| generic_error(format!("Failed to read module {}: {}", path.display(), e)))?;
+        if self.vendor.as_ref().is_some_and(|vendor| canonical.starts_with(vendor.dir())) {
+            return Ok(());
+        }
+
+        if let Some(root) = &self.root {
+            if canonical.starts_with(root) {
+                return Ok(());
+            }
+            return Err(custom_error(
+                "PermissionDenied",
+                format!("Cannot import {}: only files inside {} can be loaded", specifier, root.display()),
+            ));
+        }
+
+        match &self.permissions {
+            Some(permissions) if is_dyn_import => permissions.check_specifier(specifier),
+            _ => Ok(()),
+        }
+    }
+
+    /// Transpile a module, reusing the cached output when the source is unchanged
+    fn emit(
+        &self,
+        specifier: &ModuleSpecifier,
+        media_type: MediaType,
+        source: &str,
+    ) -> Result<(String, Option<String>), AnyError> {
+        let key = cache_key(specifier, source);
+        let code_path = self.cache_dir.join(format!("{}.js", key));
+        let map_path = self.cache_dir.join(format!("{}.js.map", key));
+
+        if let Ok(code) = fs::read_to_string(&code_path) {
+            debug!("Using cached emit for {}", specifier);
+            return Ok((code, fs::read_to_string(&map_path).ok()));
+        }
+
+        debug!("Transpiling {}", specifier);
+        let parsed = deno_ast::parse_module(ParseParams {
+            specifier: specifier.to_string(),
+            text_info: SourceTextInfo::from_string(source.to_string()),
+            media_type,
+            capture_tokens: false,
+            scope_analysis: false,
+            maybe_syntax: None,
+        })?;
+
+        let transpiled = parsed.transpile(&EmitOptions {
+            source_map: true,
+            inline_source_map: false,
+            ..Default::default()
+        })?;
+
+        if let Err(err) = write_cache(&self.cache_dir, &code_path, &transpiled.text, &map_path, transpiled.source_map.as_deref()) {
+            warn!("Failed to cache emitted JavaScript for {}: {}", specifier, err);
+        }
+
+        Ok((transpiled.text, transpiled.source_map))
+    }
+}
+
+impl ModuleLoader for TsModuleLoader {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &str,
+        _kind: ResolutionKind,
+    ) -> Result<ModuleSpecifier, AnyError> {
+        let resolved = match deno_core::resolve_import(specifier, referrer) {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                // Bare specifiers are only valid inside vendored npm packages
+                let referrer = ModuleSpecifier::parse(referrer)?;
+                return match self.vendor.as_ref().and_then(|vendor| vendor.resolve_bare(specifier, &referrer)) {
+                    Some(resolved) => resolved,
+                    None => Err(err.into()),
+                };
+            }
+        };
+
+        // Remote and registry imports are served from the vendor directory only
+        match resolved.scheme() {
+            "npm" | "jsr" | "http" | "https" => match &self.vendor {
+                Some(vendor) => vendor.resolve(&resolved),
+                None => Err(generic_error(format!(
+                    "Cannot import {}: remote imports require a vendor directory (--deno-vendor-dir)",
+                    resolved
+                ))),
+            },
+            _ => Ok(resolved),
+        }
+    }
+
+    fn load(
+        &self,
+        module_specifier: &ModuleSpecifier,
+        _maybe_referrer: Option<&ModuleSpecifier>,
+        is_dyn_import: bool,
+    ) -> Pin<Box<ModuleSourceFuture>> {
+        futures::future::ready(self.load_module(module_specifier, is_dyn_import)).boxed_local()
+    }
+}
+
+/// Cache key for the emitted output of a module
+fn cache_key(specifier: &ModuleSpecifier, source: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(EMIT_CACHE_VERSION.as_bytes());
+    hasher.update(specifier.as_str().as_bytes());
+    hasher.update(source.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Write emitted code and its source map, renaming into place so concurrent workers never see partial files
+fn write_cache(
+    cache_dir: &Path,
+    code_path: &Path,
+    code: &str,
+    map_path: &Path,
+    source_map: Option<&str>,
+) -> std::io::Result<()> {
+    fs::create_dir_all(cache_dir)?;
+
+    if let Some(source_map) = source_map {
+        write_atomic(map_path, source_map)?;
+    }
+    write_atomic(code_path, code)
+}
+
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let tmp_path = path.with_extension(format!("tmp-{}-{:?}", std::process::id(), std::thread::current().id()));
+    fs::write(&tmp_path, contents)?;
+    fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use deno_runtime::permissions::{Permissions, PermissionsOptions};
+
+    use super::*;
+
+    /// A directory with a module inside it and another next to it, only this test uses
+    fn modules(name: &str) -> (PathBuf, ModuleSpecifier, ModuleSpecifier) {
+        let dir = std::env::temp_dir().join(format!("webserver-loader-{}-{}", std::process::id(), name));
+        fs::create_dir_all(dir.join("bundle")).unwrap();
+        fs::write(dir.join("bundle/main.js"), "export const inside = true;").unwrap();
+        fs::write(dir.join("outside.json"), "{}").unwrap();
+        let inside = ModuleSpecifier::from_file_path(dir.join("bundle/main.js")).unwrap();
+        let outside = ModuleSpecifier::from_file_path(dir.join("outside.json")).unwrap();
+        (dir, inside, outside)
+    }
+
+    fn loader(dir: &Path, allow_read: Option<Vec<PathBuf>>) -> TsModuleLoader {
+        let permissions = Permissions::from_options(&PermissionsOptions {
+            allow_read,
+            prompt: false,
+            ..Default::default()
+        })
+        .unwrap();
+        TsModuleLoader::new(dir.join("cache"), SourceMapStore::default(), None, ModuleGraph::default())
+            .with_permissions(PermissionsContainer::new(permissions))
+    }
+
+    #[test]
+    fn dynamic_imports_need_read_permission() {
+        let (dir, inside, outside) = modules("dynamic");
+
+        let denied = loader(&dir, None);
+        assert!(denied.load_module(&outside, false).is_ok());
+        assert!(denied.load_module(&outside, true).is_err());
+
+        let allowed = loader(&dir, Some(vec![dir.clone()]));
+        assert!(allowed.load_module(&inside, true).is_ok());
+        assert!(allowed.load_module(&outside, true).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn confined_loaders_only_load_files_inside_their_root() {
+        let (dir, inside, outside) = modules("confined");
+        let loader = loader(&dir, Some(vec![dir.clone()])).confined_to(Some(dir.join("bundle")));
+
+        assert!(loader.load_module(&inside, false).is_ok());
+        assert!(loader.load_module(&inside, true).is_ok());
+        let err = loader.load_module(&outside, false).err().expect("outside the root");
+        assert_eq!(deno_core::error::get_custom_error_class(&err), Some("PermissionDenied"));
+        assert!(loader.load_module(&outside, true).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,24 +1,49 @@
+use std::path::Path;
 use std::process;
 use clap::Parser;
 use tracing::{info, error};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
 
 use webserver::config::{Args, ServerConfig};
+use webserver::secrets::{self, EncryptedFileSecrets};
+use webserver::reload::LogFilter;
 use webserver::server::Server;
+use webserver::settings::Settings;
 
 #[actix_web::main]
 async fn main() {
     // Parse command line arguments
     let args = Args::parse();
     
+    // Layer the settings: defaults, configuration file, environment and command line
+    let settings = Settings::load(&args);
+    
     // Initialize logging
-    setup_logging(&args.log_level);
+    let log_level = match &settings {
+        Ok(settings) => settings.server.log_level.clone(),
+        Err(_) => args.log_level.clone(),
+    };
+    let log_filter = setup_logging(log_level.as_deref().unwrap_or("info"));
+    
+    // Report the resolved configuration instead of starting the server
+    if args.check_config {
+        process::exit(check_config(&args, settings));
+    }
+    
+    // Encrypt a secrets file instead of starting the server
+    if let Some(path) = &args.seal_secrets {
+        if let Err(err) = seal_secrets(path) {
+            error!("Failed to seal secrets: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
     
     // Create server configuration
-    let config = match ServerConfig::from_args(args) {
+    let config = match settings.and_then(|settings| ServerConfig::from_settings(&settings, Settings::config_file(&args))) {
         Ok(config) => config,
-        Err(err) => {
-            error!("Failed to create server configuration: {}", err);
+        Err(errors) => {
+            error!("Failed to create server configuration: {}", errors.join("; "));
             process::exit(1);
         }
     };
@@ -28,10 +53,11 @@ async fn main() {
     info!("  Address: {}", config.addr);
     info!("  Static directory: {:?}", config.static_dir);
     info!("  Worker threads: {}", config.worker_threads);
+    info!("  AI backend: {:?}", config.ai_backend);
     info!("  Log level: {}", config.log_level);
     
     // Create and run server
-    let server = Server::new(config);
+    let server = Server::new(config).with_reload(args, log_filter);
     
     info!("Starting server...");
     if let Err(err) = server.run().await {
@@ -40,8 +66,26 @@ async fn main() {
     }
 }
 
-/// Setup logging with the specified log level
-fn setup_logging(log_level: &str) {
+/// Setup logging with the specified log level, returning a function that changes it later
+fn setup_logging(log_level: &str) -> LogFilter {
+    // Initialize tracing subscriber with a filter that can be swapped on reload
+    let (filter, handle) = reload::Layer::new(env_filter(log_level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    
+    info!("Logging initialized at {} level", log_level);
+    
+    Box::new(move |log_level| {
+        handle.reload(env_filter(log_level)).map_err(|e| e.to_string())?;
+        info!("Log level changed to {}", log_level);
+        Ok(())
+    })
+}
+
+/// Log filter for `log_level`, refined by the directives in `RUST_LOG`
+fn env_filter(log_level: &str) -> EnvFilter {
     // Parse log level
     let filter = match log_level {
         "trace" => tracing::Level::TRACE,
@@ -52,15 +96,44 @@ fn setup_logging(log_level: &str) {
         _ => tracing::Level::INFO,
     };
     
-    // Initialize tracing subscriber
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::builder()
-                .with_default_directive(filter.into())
-                .from_env_lossy()
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    EnvFilter::builder()
+        .with_default_directive(filter.into())
+        .from_env_lossy()
+}
+
+/// Print the resolved settings with secrets redacted and every validation error, returning the exit code
+fn check_config(args: &Args, settings: Result<Settings, Vec<String>>) -> i32 {
+    let result = settings.and_then(|settings| {
+        println!("{}", settings.to_toml());
+        ServerConfig::from_settings(&settings, Settings::config_file(args))
+    });
     
-    info!("Logging initialized at {} level", log_level);
-}
\ No newline at end of file
+    match result {
+        Ok(_) => {
+            info!("Configuration is valid");
+            0
+        }
+        Err(errors) => {
+            error!("Configuration has {} error(s):", errors.len());
+            for err in &errors {
+                error!("  {}", err);
+            }
+            1
+        }
+    }
+}
+
+/// Encrypt a JSON object of secrets to `<path>.enc` for the encrypted-file provider
+fn seal_secrets(path: &Path) -> webserver::Result<()> {
+    dotenv::dotenv().ok();
+    
+    let plaintext = std::fs::read(path)?;
+    let sealed = EncryptedFileSecrets::seal(&plaintext, &secrets::key_from_env()?)?;
+    
+    let mut output = path.as_os_str().to_owned();
+    output.push(".enc");
+    std::fs::write(&output, sealed)?;
+    
+    info!("Wrote encrypted secrets to {:?}", output);
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
+
+use deno_core::error::{type_error, AnyError};
+use deno_core::{op, OpState};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::client_identity::ClientIdentity;
+use crate::secrets::Secret;
+
+/// Longest key accepted by the key/value store
+const MAX_KV_KEY_LEN: usize = 512;
+
+/// Destination for chunks emitted by the streaming function currently running in the isolate
+#[derive(Default)]
+pub struct StreamSink(pub Option<mpsc::Sender<serde_json::Value>>);
+
+/// Request, function and caller of the call currently running in the isolate
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallContext {
+    pub request_id: Option<String>,
+    pub function: Option<String>,
+    /// Identity from the caller's verified client certificate
+    pub client: Option<ClientIdentity>,
+}
+
+/// Call context shared with the threads that forward the isolate's stdout and stderr
+#[derive(Clone, Default)]
+pub struct CurrentCall(pub Arc<Mutex<CallContext>>);
+
+impl CurrentCall {
+    /// Copy of the current context
+    pub fn get(&self) -> CallContext {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Replace the current context
+    pub fn set(&self, context: CallContext) {
+        *self.0.lock().unwrap() = context;
+    }
+}
+
+/// Server-side facilities shared by every isolate and exposed to scripts as `Webserver`
+#[derive(Clone, Default)]
+pub struct HostServices {
+    pub kv: KvStore,
+    pub secrets: SecretStore,
+    pub metrics: AppMetrics,
+}
+
+/// In-memory key/value store shared by all isolates, lost when the server stops
+#[derive(Clone, Default)]
+pub struct KvStore(Arc<RwLock<HashMap<String, serde_json::Value>>>);
+
+impl KvStore {
+    /// Value stored under `key`
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        self.0.read().unwrap().get(key).cloned()
+    }
+
+    /// Store `value` under `key`, replacing any previous value
+    pub fn set(&self, key: String, value: serde_json::Value) {
+        self.0.write().unwrap().insert(key, value);
+    }
+
+    /// Remove a key, returning whether it existed
+    pub fn delete(&self, key: &str) -> bool {
+        self.0.write().unwrap().remove(key).is_some()
+    }
+
+    /// Keys starting with `prefix`, in order
+    pub fn list(&self, prefix: &str) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .0
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+/// Secrets scripts may read by name, without exposing them through `Deno.env`
+#[derive(Clone, Default)]
+pub struct SecretStore {
+    secrets: Arc<HashMap<String, Secret>>,
+    /// Every read fails, for isolates whose heap is written to disk
+    withheld: bool,
+}
+
+impl SecretStore {
+    /// Create a store holding `secrets`, keyed by name
+    pub fn new(secrets: HashMap<String, Secret>) -> Self {
+        Self {
+            secrets: Arc::new(secrets),
+            withheld: false,
+        }
+    }
+
+    /// Create a store that refuses every read
+    pub fn withheld() -> Self {
+        Self {
+            withheld: true,
+            ..Default::default()
+        }
+    }
+
+    /// Secret named `name`, if configured
+    pub fn get(&self, name: &str) -> Option<&Secret> {
+        self.secrets.get(name)
+    }
+}
+
+/// Counters incremented by scripts, reported by the status endpoint
+#[derive(Clone, Default)]
+pub struct AppMetrics(Arc<Mutex<BTreeMap<String, u64>>>);
+
+impl AppMetrics {
+    /// Add `by` to the counter `name`, creating it at zero
+    pub fn increment(&self, name: &str, by: u64) {
+        let mut counters = self.0.lock().unwrap();
+        let counter = counters.entry(name.to_string()).or_default();
+        *counter = counter.saturating_add(by);
+    }
+
+    /// Current value of every counter
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            counters: self.0.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Counter values at a point in time
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub counters: BTreeMap<String, u64>,
+}
+
+/// Emit a line of Deno output as a `tracing` event tagged with its call context
+///
+/// `fields` holds structured data attached by `Webserver.log`, recorded as JSON.
+pub fn log_output(level: &str, message: &str, fields: Option<&serde_json::Value>, context: &CallContext) {
+    let request_id = context.request_id.as_deref().unwrap_or("-");
+    let function = context.function.as_deref().unwrap_or("-");
+    let fields = fields.map(ToString::to_string).unwrap_or_default();
+
+    match level {
+        "error" => tracing::error!(target: "deno", request_id, function, fields, "{}", message),
+        "warn" => tracing::warn!(target: "deno", request_id, function, fields, "{}", message),
+        "debug" => tracing::debug!(target: "deno", request_id, function, fields, "{}", message),
+        "trace" => tracing::trace!(target: "deno", request_id, function, fields, "{}", message),
+        _ => tracing::info!(target: "deno", request_id, function, fields, "{}", message),
+    }
+}
+
+/// Push a single stream chunk to the HTTP response
+///
+/// Resolves to `false` once the client has gone away so the script can stop producing.
+/// Waits while the bounded channel is full, which applies backpressure to the script.
+#[op]
+async fn op_stream_chunk(state: Rc<RefCell<OpState>>, chunk: serde_json::Value) -> Result<bool, AnyError> {
+    let sender = state.borrow().borrow::<StreamSink>().0.clone();
+
+    match sender {
+        Some(sender) => Ok(sender.send(chunk).await.is_ok()),
+        None => Err(type_error("No stream is active for this call")),
+    }
+}
+
+/// Log a message at the given level, with optional structured fields
+#[op]
+fn op_log(state: &mut OpState, level: String, message: String, fields: Option<serde_json::Value>) {
+    let context = state.borrow::<CurrentCall>().get();
+    log_output(&level, &message, fields.as_ref().filter(|fields| !fields.is_null()), &context);
+}
+
+#[op]
+fn op_kv_get(state: &mut OpState, key: String) -> Result<Option<serde_json::Value>, AnyError> {
+    validate_kv_key(&key)?;
+    Ok(state.borrow::<KvStore>().get(&key))
+}
+
+#[op]
+fn op_kv_set(state: &mut OpState, key: String, value: serde_json::Value) -> Result<(), AnyError> {
+    validate_kv_key(&key)?;
+    state.borrow::<KvStore>().set(key, value);
+    Ok(())
+}
+
+#[op]
+fn op_kv_delete(state: &mut OpState, key: String) -> Result<bool, AnyError> {
+    validate_kv_key(&key)?;
+    Ok(state.borrow::<KvStore>().delete(&key))
+}
+
+#[op]
+fn op_kv_list(state: &mut OpState, prefix: String) -> Vec<String> {
+    state.borrow::<KvStore>().list(&prefix)
+}
+
+/// Context of the current call, or empty fields outside of a call
+#[op]
+fn op_call_context(state: &mut OpState) -> CallContext {
+    state.borrow::<CurrentCall>().get()
+}
+
+/// Look up a secret by name, returning `null` when it is not configured
+#[op]
+fn op_secret_get(state: &mut OpState, name: String) -> Result<Option<String>, AnyError> {
+    let secrets = state.borrow::<SecretStore>();
+    if secrets.withheld {
+        return Err(type_error("Secrets cannot be read while a startup snapshot is built"));
+    }
+    Ok(secrets.get(&name).map(|secret| secret.expose().to_string()))
+}
+
+#[op]
+fn op_metric_increment(state: &mut OpState, name: String, by: Option<u32>) -> Result<(), AnyError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | ':')) {
+        return Err(type_error(format!("Invalid metric name: {:?}", name)));
+    }
+    state.borrow::<AppMetrics>().increment(&name, u64::from(by.unwrap_or(1)));
+    Ok(())
+}
+
+fn validate_kv_key(key: &str) -> Result<(), AnyError> {
+    if key.is_empty() || key.len() > MAX_KV_KEY_LEN {
+        return Err(type_error(format!("Key must be between 1 and {} bytes long", MAX_KV_KEY_LEN)));
+    }
+    Ok(())
+}
+
+deno_core::extension!(
+    webserver_ops,
+    ops = [
+        op_stream_chunk,
+        op_log,
+        op_kv_get,
+        op_kv_set,
+        op_kv_delete,
+        op_kv_list,
+        op_secret_get,
+        op_metric_increment,
+        op_call_context,
+    ],
+    options = {
+        services: HostServices,
+    },
+    state = |state, options| {
+        state.put(StreamSink::default());
+        state.put(CurrentCall::default());
+        state.put(options.services.kv);
+        state.put(options.services.secrets);
+        state.put(options.services.metrics);
+    },
+);
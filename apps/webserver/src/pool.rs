@@ -0,0 +1,898 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use deno_runtime::worker::MainWorker;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, error, info, warn};
+
+use crate::client_identity::ClientIdentity;
+use crate::deno::DenoRuntime;
+use crate::error::{Result, ServerError};
+use crate::ops::CallContext;
+
+/// Delay before the first restart attempt after a failed initialization
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound for the restart backoff
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A unit of work sent to a worker thread
+enum Job {
+    /// Call a global function in the isolate and reply with its result
+    Call {
+        function: String,
+        context: CallContext,
+        args: serde_json::Value,
+        reply: oneshot::Sender<Result<serde_json::Value>>,
+        ticket: InFlight,
+    },
+    /// Call a streaming function, forwarding its chunks to `sink`
+    Stream {
+        function: String,
+        context: CallContext,
+        args: serde_json::Value,
+        sink: mpsc::Sender<serde_json::Value>,
+        reply: oneshot::Sender<Result<()>>,
+        ticket: InFlight,
+    },
+    /// Replace the isolate with a fresh one that loads the application from disk again
+    Reload {
+        generation: u64,
+        reply: oneshot::Sender<Result<()>>,
+    },
+}
+
+impl Job {
+    /// Fail a job that cannot be run
+    fn reject(self, err: ServerError) {
+        match self {
+            Job::Call { reply, .. } => {
+                let _ = reply.send(Err(err));
+            }
+            Job::Stream { reply, .. } | Job::Reload { reply, .. } => {
+                let _ = reply.send(Err(err));
+            }
+        }
+    }
+}
+
+/// Keeps a worker out of the idle list until a job dispatched to it has finished
+///
+/// A caller that stops waiting for its reply drops its `PooledWorker`, but the
+/// isolate is still running the job. The worker is released once both the guard
+/// and every ticket it handed out are gone.
+struct InFlight {
+    pool: Weak<DenoPool>,
+    index: usize,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.finish_job(self.index);
+        }
+    }
+}
+
+/// Counts a checkout as waiting until it gets a permit or is cancelled
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Lifecycle state of a pooled worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    /// Running `DenoRuntime::init` for the first time
+    Starting,
+    /// Serving calls
+    Ready,
+    /// Rebuilding after the isolate was terminated or poisoned
+    Restarting,
+    /// Initialization failed; waiting for the next attempt
+    Failed,
+}
+
+impl WorkerState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => WorkerState::Ready,
+            2 => WorkerState::Restarting,
+            3 => WorkerState::Failed,
+            _ => WorkerState::Starting,
+        }
+    }
+}
+
+/// Health counters for a single pooled worker
+#[derive(Default)]
+struct WorkerHealth {
+    state: AtomicU8,
+    busy: AtomicBool,
+    in_flight: AtomicUsize,
+    calls: AtomicU64,
+    failures: AtomicU64,
+    restarts: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl WorkerHealth {
+    fn state(&self) -> WorkerState {
+        WorkerState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    fn set_state(&self, state: WorkerState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    fn record_error(&self, err: &ServerError) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(err.to_string());
+    }
+}
+
+/// Handle to a Deno isolate running on its own thread
+struct WorkerSlot {
+    id: usize,
+    jobs: mpsc::UnboundedSender<Job>,
+    health: Arc<WorkerHealth>,
+}
+
+/// Queue wait metrics for pool checkouts
+#[derive(Default)]
+struct PoolMetrics {
+    checkouts: AtomicU64,
+    waiting: AtomicUsize,
+    total_wait_us: AtomicU64,
+    max_wait_us: AtomicU64,
+}
+
+/// Snapshot of a single worker's health
+#[derive(Debug, Serialize)]
+pub struct WorkerStats {
+    pub id: usize,
+    pub state: WorkerState,
+    pub busy: bool,
+    pub calls: u64,
+    pub failures: u64,
+    pub restarts: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Snapshot of the pool state
+#[derive(Debug, Serialize)]
+pub struct PoolStats {
+    pub size: usize,
+    pub ready: usize,
+    pub idle: usize,
+    pub waiting: usize,
+    pub checkouts: u64,
+    pub avg_wait_ms: f64,
+    pub max_wait_ms: f64,
+    pub workers: Vec<WorkerStats>,
+}
+
+/// Pool of Deno isolates, each owned by a dedicated thread
+///
+/// `MainWorker` is not `Send`, so every isolate lives on its own single-threaded
+/// Tokio runtime and receives calls over a channel. Handlers check a worker out
+/// of the pool, which lets independent requests run concurrently.
+///
+/// Each worker thread supervises its isolate: failed initializations are retried
+/// with exponential backoff, and isolates that were terminated or panicked are
+/// rebuilt. Only ready workers are handed out.
+pub struct DenoPool {
+    workers: Vec<WorkerSlot>,
+    idle: Mutex<Vec<usize>>,
+    available: Arc<Semaphore>,
+    metrics: PoolMetrics,
+    generation: AtomicU64,
+}
+
+/// A worker checked out of the pool
+///
+/// Returned on drop, or once the last job dispatched through it has finished if
+/// the caller stopped waiting for a reply.
+pub struct PooledWorker {
+    pool: Arc<DenoPool>,
+    index: usize,
+    permit: Option<OwnedSemaphorePermit>,
+    request_id: Option<String>,
+    client: Option<ClientIdentity>,
+}
+
+impl DenoPool {
+    /// Start `size` supervised isolates and wait for each one's first start attempt
+    pub async fn new(runtime: Arc<DenoRuntime>, size: usize) -> Result<Arc<Self>> {
+        let size = size.max(1);
+        let (pool, receivers) = Self::with_slots(size);
+        let pool = Arc::new(pool);
+
+        let mut first_starts = Vec::with_capacity(size);
+        for (id, rx) in receivers.into_iter().enumerate() {
+            let (started_tx, started_rx) = oneshot::channel();
+            spawn_worker_thread(
+                id,
+                runtime.clone(),
+                pool.workers[id].health.clone(),
+                Arc::downgrade(&pool),
+                rx,
+                started_tx,
+            )?;
+            first_starts.push(started_rx);
+        }
+
+        let mut ready = 0;
+        for started_rx in first_starts {
+            if let Ok(true) = started_rx.await {
+                ready += 1;
+            }
+        }
+
+        if ready == 0 {
+            warn!("No Deno worker could be started; retrying in the background");
+        } else {
+            info!("Deno worker pool started with {}/{} isolate(s) ready", ready, size);
+        }
+
+        Ok(pool)
+    }
+
+    /// Create the worker slots and the receiving ends of their job queues
+    ///
+    /// Workers add themselves to the idle list once they are ready.
+    fn with_slots(size: usize) -> (Self, Vec<mpsc::UnboundedReceiver<Job>>) {
+        let mut workers = Vec::with_capacity(size);
+        let mut receivers = Vec::with_capacity(size);
+
+        for id in 0..size {
+            let (jobs, rx) = mpsc::unbounded_channel();
+            workers.push(WorkerSlot {
+                id,
+                jobs,
+                health: Arc::new(WorkerHealth::default()),
+            });
+            receivers.push(rx);
+        }
+
+        let pool = Self {
+            workers,
+            idle: Mutex::new(Vec::with_capacity(size)),
+            available: Arc::new(Semaphore::new(0)),
+            metrics: PoolMetrics::default(),
+            generation: AtomicU64::new(0),
+        };
+
+        (pool, receivers)
+    }
+
+    /// Number of isolates in the pool
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Number of isolates currently able to serve calls
+    pub fn ready_count(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|slot| slot.health.state() == WorkerState::Ready)
+            .count()
+    }
+
+    /// Wait for a free worker and check it out
+    ///
+    /// Fails immediately when no worker is ready, rather than waiting for a restart.
+    pub async fn checkout(self: &Arc<Self>) -> Result<PooledWorker> {
+        if self.ready_count() == 0 {
+            return Err(ServerError::DenoUnavailable("no Deno worker is ready".to_string()));
+        }
+
+        let started = Instant::now();
+        let waiting = Waiting::new(&self.metrics.waiting);
+
+        let permit = self
+            .available
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("worker pool semaphore is never closed");
+
+        drop(waiting);
+        self.record_wait(started.elapsed());
+
+        // Permits are only issued for ready workers in the idle list
+        let index = {
+            let mut idle = self.idle.lock().unwrap();
+            let index = idle.pop().expect("a permit always has an idle worker");
+            self.workers[index].health.busy.store(true, Ordering::Relaxed);
+            index
+        };
+
+        Ok(PooledWorker {
+            pool: self.clone(),
+            index,
+            permit: Some(permit),
+            request_id: None,
+            client: None,
+        })
+    }
+
+    /// Rebuild every isolate from the application's current sources
+    ///
+    /// Each worker finishes the call it is running on its old isolate, then builds
+    /// a fresh one and swaps it in. A worker whose fresh isolate fails to load keeps
+    /// the old one, and the first such error is returned.
+    pub async fn reload(&self) -> Result<()> {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut replies = Vec::with_capacity(self.workers.len());
+
+        for slot in &self.workers {
+            let (reply, rx) = oneshot::channel();
+            if slot.jobs.send(Job::Reload { generation, reply }).is_ok() {
+                replies.push(rx);
+            }
+        }
+
+        let mut result = Ok(());
+        for rx in replies {
+            if let Ok(Err(err)) = rx.await {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Get a snapshot of the pool and worker health
+    pub fn stats(&self) -> PoolStats {
+        let checkouts = self.metrics.checkouts.load(Ordering::Relaxed);
+        let total_wait_us = self.metrics.total_wait_us.load(Ordering::Relaxed);
+        let avg_wait_ms = if checkouts == 0 {
+            0.0
+        } else {
+            total_wait_us as f64 / checkouts as f64 / 1000.0
+        };
+
+        PoolStats {
+            size: self.workers.len(),
+            ready: self.ready_count(),
+            idle: self.idle.lock().unwrap().len(),
+            waiting: self.metrics.waiting.load(Ordering::Relaxed),
+            checkouts,
+            avg_wait_ms,
+            max_wait_ms: self.metrics.max_wait_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            workers: self
+                .workers
+                .iter()
+                .map(|slot| WorkerStats {
+                    id: slot.id,
+                    state: slot.health.state(),
+                    busy: slot.health.busy.load(Ordering::Relaxed),
+                    calls: slot.health.calls.load(Ordering::Relaxed),
+                    failures: slot.health.failures.load(Ordering::Relaxed),
+                    restarts: slot.health.restarts.load(Ordering::Relaxed),
+                    last_error: slot.health.last_error.lock().unwrap().clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Mark a worker ready and make it available for checkout
+    fn mark_ready(&self, index: usize) {
+        let health = &self.workers[index].health;
+        let mut idle = self.idle.lock().unwrap();
+        health.set_state(WorkerState::Ready);
+
+        // A worker that is still checked out is released by its guard or its last job instead
+        if !health.busy.load(Ordering::Relaxed) && health.in_flight.load(Ordering::Relaxed) == 0 {
+            idle.push(index);
+            self.available.add_permits(1);
+        }
+    }
+
+    /// Record that a job has finished, releasing the worker if its caller already let go
+    fn finish_job(&self, index: usize) {
+        let health = &self.workers[index].health;
+        let mut idle = self.idle.lock().unwrap();
+        let remaining = health.in_flight.fetch_sub(1, Ordering::Relaxed) - 1;
+
+        if remaining == 0 && !health.busy.load(Ordering::Relaxed) && health.state() == WorkerState::Ready {
+            idle.push(index);
+            self.available.add_permits(1);
+        }
+    }
+
+    /// Take a checked out worker out of rotation until it has been rebuilt
+    fn mark_down(&self, index: usize) {
+        let _idle = self.idle.lock().unwrap();
+        self.workers[index].health.set_state(WorkerState::Restarting);
+    }
+
+    fn record_wait(&self, wait: Duration) {
+        let wait_us = wait.as_micros() as u64;
+        self.metrics.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.metrics.total_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+        self.metrics.max_wait_us.fetch_max(wait_us, Ordering::Relaxed);
+
+        if wait > Duration::from_millis(100) {
+            debug!("Waited {:?} for a Deno worker", wait);
+        }
+    }
+}
+
+impl PooledWorker {
+    /// Index of the checked out worker
+    pub fn id(&self) -> usize {
+        self.index
+    }
+
+    /// Tag the output of calls made through this worker with an HTTP request ID
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Tell calls made through this worker which client certificate the caller presented
+    pub fn with_client(mut self, client: Option<ClientIdentity>) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Hold the worker until the job being dispatched has finished
+    fn ticket(&self) -> InFlight {
+        self.pool.workers[self.index].health.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight {
+            pool: Arc::downgrade(&self.pool),
+            index: self.index,
+        }
+    }
+
+    /// Context of a call to `function`, visible to the script as `Webserver.context`
+    fn context(&self, function: &str) -> CallContext {
+        CallContext {
+            request_id: self.request_id.clone(),
+            function: Some(function.to_string()),
+            client: self.client.clone(),
+        }
+    }
+
+    /// Call a global function in the checked out isolate and deserialize its result
+    pub async fn call<A, R>(&self, function: &str, args: &A) -> Result<R>
+    where
+        A: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let (reply, rx) = oneshot::channel();
+        let job = Job::Call {
+            function: function.to_string(),
+            context: self.context(function),
+            args: serde_json::to_value(args)?,
+            reply,
+            ticket: self.ticket(),
+        };
+
+        let result = self.dispatch(function, job, rx).await?;
+        serde_json::from_value(result)
+            .map_err(|e| ServerError::DenoExecution(format!("Invalid result from {}: {}", function, e)))
+    }
+
+    /// Call a streaming function in the checked out isolate
+    ///
+    /// Chunks are sent to `sink` while the function runs; the returned future
+    /// completes once the function has finished producing them.
+    pub async fn stream<A>(&self, function: &str, args: &A, sink: mpsc::Sender<serde_json::Value>) -> Result<()>
+    where
+        A: Serialize + ?Sized,
+    {
+        let (reply, rx) = oneshot::channel();
+        let job = Job::Stream {
+            function: function.to_string(),
+            context: self.context(function),
+            args: serde_json::to_value(args)?,
+            sink,
+            reply,
+            ticket: self.ticket(),
+        };
+
+        self.dispatch(function, job, rx).await
+    }
+
+    /// Send a job to the worker thread and wait for its reply
+    async fn dispatch<T>(&self, function: &str, job: Job, rx: oneshot::Receiver<Result<T>>) -> Result<T> {
+        let slot = &self.pool.workers[self.index];
+        slot.health.calls.fetch_add(1, Ordering::Relaxed);
+
+        if slot.jobs.send(job).is_err() {
+            let err = ServerError::DenoUnavailable(format!("Deno worker {} is not running", slot.id));
+            slot.health.record_error(&err);
+            return Err(err);
+        }
+
+        let result = match rx.await {
+            Ok(result) => result,
+            Err(_) => Err(ServerError::DenoUnavailable(format!(
+                "Deno worker {} stopped while handling {}",
+                slot.id, function
+            ))),
+        };
+
+        if let Err(err) = &result {
+            slot.health.record_error(err);
+        }
+
+        result
+    }
+}
+
+impl Drop for PooledWorker {
+    fn drop(&mut self) {
+        let health = &self.pool.workers[self.index].health;
+        let mut idle = self.pool.idle.lock().unwrap();
+        health.busy.store(false, Ordering::Relaxed);
+
+        if health.state() == WorkerState::Ready && health.in_flight.load(Ordering::Relaxed) == 0 {
+            idle.push(self.index);
+        } else if let Some(permit) = self.permit.take() {
+            // A new permit is issued once the running job finishes or the worker has been rebuilt
+            permit.forget();
+        }
+    }
+}
+
+/// Why a worker stopped serving calls
+enum ServeExit {
+    /// The pool was dropped and the job channel closed
+    Closed,
+    /// The isolate was terminated or panicked and must be rebuilt
+    Poisoned,
+}
+
+/// Spawn the thread that owns and supervises one isolate
+fn spawn_worker_thread(
+    id: usize,
+    runtime: Arc<DenoRuntime>,
+    health: Arc<WorkerHealth>,
+    pool: Weak<DenoPool>,
+    jobs: mpsc::UnboundedReceiver<Job>,
+    started: oneshot::Sender<bool>,
+) -> Result<()> {
+    thread::Builder::new()
+        .name(format!("deno-worker-{}", id))
+        .spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(err) => {
+                    error!("Failed to create runtime for Deno worker {}: {}", id, err);
+                    health.set_state(WorkerState::Failed);
+                    health.record_error(&ServerError::Io(err));
+                    let _ = started.send(false);
+                    return;
+                }
+            };
+
+            let local = tokio::task::LocalSet::new();
+            local.block_on(&rt, supervise(id, runtime, health, pool, jobs, started));
+        })?;
+
+    Ok(())
+}
+
+/// Keep an isolate running, rebuilding it whenever it fails
+async fn supervise(
+    id: usize,
+    runtime: Arc<DenoRuntime>,
+    health: Arc<WorkerHealth>,
+    pool: Weak<DenoPool>,
+    mut jobs: mpsc::UnboundedReceiver<Job>,
+    started: oneshot::Sender<bool>,
+) {
+    let mut started = Some(started);
+    let mut backoff = INITIAL_RESTART_BACKOFF;
+    let mut pending_reload: Option<oneshot::Sender<Result<()>>> = None;
+
+    loop {
+        let Some(generation) = pool.upgrade().map(|pool| pool.generation.load(Ordering::SeqCst)) else {
+            break;
+        };
+
+        match runtime.init().await {
+            Ok(worker) => {
+                if health.state() != WorkerState::Starting {
+                    info!("Deno worker {} restarted", id);
+                }
+                backoff = INITIAL_RESTART_BACKOFF;
+
+                let Some(strong) = pool.upgrade() else { break };
+                strong.mark_ready(id);
+                drop(strong);
+
+                if let Some(started) = started.take() {
+                    let _ = started.send(true);
+                }
+                if let Some(reply) = pending_reload.take() {
+                    let _ = reply.send(Ok(()));
+                }
+
+                match serve(id, &runtime, &pool, worker, generation, &mut jobs).await {
+                    ServeExit::Closed => break,
+                    ServeExit::Poisoned => {
+                        health.restarts.fetch_add(1, Ordering::Relaxed);
+                        info!("Rebuilding Deno worker {}", id);
+                    }
+                }
+            }
+            Err(err) => {
+                error!("Deno worker {} failed to initialize: {}; retrying in {:?}", id, err, backoff);
+                health.set_state(WorkerState::Failed);
+                health.record_error(&err);
+
+                if let Some(started) = started.take() {
+                    let _ = started.send(false);
+                }
+                if let Some(reply) = pending_reload.take() {
+                    let _ = reply.send(Err(err));
+                }
+
+                // Wait out the backoff, but retry at once when a reload is requested
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    job = jobs.recv() => match job {
+                        Some(Job::Reload { reply, .. }) => pending_reload = Some(reply),
+                        Some(job) => job.reject(ServerError::DenoUnavailable(format!("Deno worker {} is restarting", id))),
+                        None => break,
+                    },
+                }
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+                health.restarts.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    debug!("Deno worker {} shutting down", id);
+}
+
+/// Serve calls on an isolate until the pool closes or the isolate is poisoned
+async fn serve(
+    id: usize,
+    runtime: &DenoRuntime,
+    pool: &Weak<DenoPool>,
+    mut worker: MainWorker,
+    mut generation: u64,
+    jobs: &mut mpsc::UnboundedReceiver<Job>,
+) -> ServeExit {
+    while let Some(job) = jobs.recv().await {
+        let poisoned = match job {
+            Job::Call { function, context, args, reply, ticket: _ticket } => {
+                let request_id = context.request_id.clone();
+                let result = AssertUnwindSafe(runtime.execute_function::<_, serde_json::Value>(
+                    &mut worker,
+                    &function,
+                    context,
+                    &args,
+                ))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|_| Err(ServerError::DenoPanic(function.clone())));
+                if let Err(err) = &result {
+                    warn!(request_id = request_id.as_deref().unwrap_or("-"), "Deno worker {} failed to execute {}: {}", id, function, err);
+                }
+                let poisoned = is_poisoned(&result);
+                if poisoned {
+                    take_down(pool, id);
+                }
+                let _ = reply.send(result);
+                poisoned
+            }
+            Job::Stream { function, context, args, sink, reply, ticket: _ticket } => {
+                let request_id = context.request_id.clone();
+                let result = AssertUnwindSafe(runtime.execute_stream_function(
+                    &mut worker,
+                    &function,
+                    context,
+                    &args,
+                    sink,
+                ))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|_| Err(ServerError::DenoPanic(function.clone())));
+                if let Err(err) = &result {
+                    warn!(request_id = request_id.as_deref().unwrap_or("-"), "Deno worker {} failed to stream {}: {}", id, function, err);
+                }
+                let poisoned = is_poisoned(&result);
+                if poisoned {
+                    take_down(pool, id);
+                }
+                let _ = reply.send(result);
+                poisoned
+            }
+            Job::Reload { generation: requested, reply } => {
+                // Isolates built after the reload was requested already run the new code
+                if requested > generation {
+                    match runtime.init().await {
+                        Ok(fresh) => {
+                            worker = fresh;
+                            generation = requested;
+                            info!("Deno worker {} reloaded", id);
+                            let _ = reply.send(Ok(()));
+                        }
+                        Err(err) => {
+                            error!("Deno worker {} failed to reload, keeping the previous isolate: {}", id, err);
+                            let _ = reply.send(Err(err));
+                        }
+                    }
+                } else {
+                    let _ = reply.send(Ok(()));
+                }
+                false
+            }
+        };
+
+        // A terminated or panicked isolate cannot be trusted with further scripts
+        if poisoned {
+            return ServeExit::Poisoned;
+        }
+    }
+
+    ServeExit::Closed
+}
+
+fn is_poisoned<T>(result: &Result<T>) -> bool {
+    matches!(result, Err(err) if err.is_isolate_terminated() || matches!(err, ServerError::DenoPanic(_)))
+}
+
+/// Mark the worker as restarting before its caller is released
+fn take_down(pool: &Weak<DenoPool>, id: usize) {
+    if let Some(pool) = pool.upgrade() {
+        pool.mark_down(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pool whose job queues are read by the test instead of worker threads
+    fn detached_pool(size: usize) -> (Arc<DenoPool>, Vec<mpsc::UnboundedReceiver<Job>>) {
+        let (pool, receivers) = DenoPool::with_slots(size);
+        let pool = Arc::new(pool);
+        for index in 0..size {
+            pool.mark_ready(index);
+        }
+        (pool, receivers)
+    }
+
+    fn idle(pool: &DenoPool) -> Vec<usize> {
+        let mut idle = pool.idle.lock().unwrap().clone();
+        idle.sort_unstable();
+        idle
+    }
+
+    /// Dispatch a call and give up on it before the worker replies
+    fn abandon_call(worker: &PooledWorker) {
+        let call = worker.call::<_, serde_json::Value>("handler", &());
+        assert!(call.now_or_never().is_none());
+    }
+
+    #[test]
+    fn checkout_and_release_return_workers_to_the_idle_list() {
+        let (pool, _jobs) = detached_pool(2);
+        assert_eq!(idle(&pool), vec![0, 1]);
+        assert_eq!(pool.available.available_permits(), 2);
+
+        let first = pool.checkout().now_or_never().unwrap().unwrap();
+        let second = pool.checkout().now_or_never().unwrap().unwrap();
+        assert!(idle(&pool).is_empty());
+        assert_eq!(pool.available.available_permits(), 0);
+        assert!(pool.stats().workers.iter().all(|worker| worker.busy));
+
+        drop(first);
+        drop(second);
+        assert_eq!(idle(&pool), vec![0, 1]);
+        assert_eq!(pool.available.available_permits(), 2);
+        assert_eq!(pool.stats().checkouts, 2);
+    }
+
+    #[test]
+    fn cancelled_checkouts_stop_waiting() {
+        let (pool, _jobs) = detached_pool(1);
+        let worker = pool.checkout().now_or_never().unwrap().unwrap();
+
+        assert!(pool.checkout().now_or_never().is_none());
+        assert_eq!(pool.stats().waiting, 0);
+
+        drop(worker);
+        assert_eq!(pool.available.available_permits(), 1);
+    }
+
+    #[test]
+    fn workers_stay_checked_out_until_an_abandoned_job_finishes() {
+        let (pool, mut jobs) = detached_pool(1);
+        let worker = pool.checkout().now_or_never().unwrap().unwrap();
+        abandon_call(&worker);
+        drop(worker);
+
+        assert!(idle(&pool).is_empty());
+        assert_eq!(pool.available.available_permits(), 0);
+
+        drop(jobs[0].try_recv().unwrap());
+        assert_eq!(idle(&pool), vec![0]);
+        assert_eq!(pool.available.available_permits(), 1);
+    }
+
+    #[test]
+    fn finished_jobs_leave_held_workers_checked_out() {
+        let (pool, mut jobs) = detached_pool(1);
+        let worker = pool.checkout().now_or_never().unwrap().unwrap();
+        abandon_call(&worker);
+
+        drop(jobs[0].try_recv().unwrap());
+        assert!(idle(&pool).is_empty());
+
+        drop(worker);
+        assert_eq!(idle(&pool), vec![0]);
+        assert_eq!(pool.available.available_permits(), 1);
+    }
+
+    #[test]
+    fn rebuilt_workers_are_released_once() {
+        let (pool, mut jobs) = detached_pool(1);
+        let worker = pool.checkout().now_or_never().unwrap().unwrap();
+        abandon_call(&worker);
+        drop(worker);
+
+        // The abandoned job terminates the isolate, which is then rebuilt
+        let job = jobs[0].try_recv().unwrap();
+        pool.mark_down(0);
+        drop(job);
+        assert!(idle(&pool).is_empty());
+        assert_eq!(pool.available.available_permits(), 0);
+
+        pool.mark_ready(0);
+        assert_eq!(idle(&pool), vec![0]);
+        assert_eq!(pool.available.available_permits(), 1);
+    }
+
+    #[test]
+    fn workers_rebuilt_while_checked_out_are_released_by_their_guard() {
+        let (pool, _jobs) = detached_pool(1);
+        let worker = pool.checkout().now_or_never().unwrap().unwrap();
+
+        pool.mark_down(0);
+        pool.mark_ready(0);
+        assert!(idle(&pool).is_empty());
+        assert_eq!(pool.available.available_permits(), 0);
+
+        drop(worker);
+        assert_eq!(idle(&pool), vec![0]);
+        assert_eq!(pool.available.available_permits(), 1);
+    }
+
+    #[test]
+    fn checkouts_fail_without_a_ready_worker() {
+        let (pool, _jobs) = detached_pool(1);
+        pool.mark_down(0);
+
+        let err = pool.checkout().now_or_never().unwrap().err().unwrap();
+        assert!(matches!(err, ServerError::DenoUnavailable(_)));
+    }
+}
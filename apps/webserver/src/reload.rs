@@ -0,0 +1,193 @@
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::admin::AdminKeys;
+#[cfg(feature = "deno")]
+use crate::apps::DenoApps;
+use crate::config::{Args, ServerConfig};
+use crate::limits::Limits;
+use crate::settings::Settings;
+
+/// Replaces the log filter with one for the given log level
+pub type LogFilter = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Outcome of a successful reload, returned by `POST /api/admin/reload`
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Settings that changed and now apply
+    pub reloaded: Vec<&'static str>,
+
+    /// Settings that changed but keep their old value until the server restarts
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Re-reads the configuration and swaps in the parts that can change while the server runs
+///
+/// The log filter, body limits, admin keys and mounted modules are replaced without
+/// touching open connections. A configuration that fails validation is rejected as a
+/// whole, and the server keeps running with the old one.
+pub struct Reloader {
+    args: Args,
+    log_filter: LogFilter,
+    config: RwLock<Arc<ServerConfig>>,
+    limits: Limits,
+    admin_keys: AdminKeys,
+    #[cfg(feature = "deno")]
+    deno: DenoApps,
+    /// Held for the length of a reload so concurrent ones do not interleave
+    reloading: Mutex<()>,
+}
+
+impl Reloader {
+    /// Reload the settings layered from `args` into the running server, starting from `config`
+    pub fn new(
+        args: Args,
+        log_filter: LogFilter,
+        config: Arc<ServerConfig>,
+        limits: Limits,
+        admin_keys: AdminKeys,
+        #[cfg(feature = "deno")] deno: DenoApps,
+    ) -> Self {
+        Self {
+            args,
+            log_filter,
+            config: RwLock::new(config),
+            limits,
+            admin_keys,
+            #[cfg(feature = "deno")]
+            deno,
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// The configuration in effect now
+    pub fn current(&self) -> Arc<ServerConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Load and validate the configuration, then apply every reloadable change
+    ///
+    /// Returns every validation error when the new configuration is rejected.
+    pub async fn reload(&self) -> Result<ReloadReport, Vec<String>> {
+        let _reloading = self.reloading.lock().await;
+
+        let result = Settings::load(&self.args)
+            .and_then(|settings| ServerConfig::from_settings(&settings, Settings::config_file(&self.args)));
+        let config = match result {
+            Ok(config) => config,
+            Err(errors) => {
+                error!("Configuration reload rejected: {}", errors.join("; "));
+                return Err(errors);
+            }
+        };
+        let current = self.current();
+        let mut report = ReloadReport::default();
+
+        // Start the new mounts first, so a module that fails to load leaves everything unchanged
+        #[cfg(feature = "deno")]
+        if config.mounts != current.mounts {
+            let table = self
+                .deno
+                .routes
+                .current()
+                .restart(&self.deno.runtime, &config.mounts, current.watch)
+                .await
+                .map_err(|err| {
+                    error!("Configuration reload rejected: {}", err);
+                    vec![err.to_string()]
+                })?;
+            self.deno.routes.replace(table);
+            report.reloaded.push("deno.routes");
+        }
+
+        // The old level stays in effect, and is reported as such, if the filter cannot be swapped
+        let mut log_level = current.log_level.clone();
+        if config.log_level != current.log_level {
+            match (self.log_filter)(&config.log_level) {
+                Ok(()) => {
+                    log_level = config.log_level;
+                    report.reloaded.push("server.log_level");
+                }
+                Err(err) => error!("Failed to change the log level: {}", err),
+            }
+        }
+
+        if config.max_json_bytes != current.max_json_bytes || config.max_upload_bytes != current.max_upload_bytes {
+            self.limits.set(config.max_json_bytes, config.max_upload_bytes);
+            report.reloaded.push("limits");
+        }
+
+        if config.admin_keys != current.admin_keys {
+            self.admin_keys.replace(config.admin_keys.clone());
+            report.reloaded.push("auth.admin_keys");
+        }
+
+        report.restart_required = current.restart_required(&config);
+        for name in &report.restart_required {
+            warn!("{} changed, but it only takes effect after a restart", name);
+        }
+
+        info!("Configuration reloaded; changed: {:?}", report.reloaded);
+
+        // Keep the old value of every setting that needs a restart, so it is reported again
+        let applied = ServerConfig {
+            log_level,
+            max_json_bytes: config.max_json_bytes,
+            max_upload_bytes: config.max_upload_bytes,
+            admin_keys: config.admin_keys,
+            routes_file: config.routes_file,
+            mounts: config.mounts,
+            ..(*current).clone()
+        };
+        *self.config.write().unwrap() = Arc::new(applied);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// A reloader whose log filter fails, running at `info` while `args` ask for `debug`
+    async fn reloader(name: &str) -> Reloader {
+        let dir = std::env::temp_dir().join(format!("webserver-reload-{}-{}", std::process::id(), name));
+        let args = Args::parse_from([
+            "webserver".to_string(),
+            "--log-level=debug".to_string(),
+            "--ai-backend=mock".to_string(),
+            format!("--static-dir={}", std::env::temp_dir().display()),
+            format!("--data-dir={}", dir.display()),
+            format!("--deno-cache-dir={}", dir.join("cache").display()),
+        ]);
+        let settings = Settings::load(&args).expect("test settings load");
+        let mut config = ServerConfig::from_settings(&settings, None).expect("test settings are valid");
+        config.log_level = "info".to_string();
+        let config = Arc::new(config);
+
+        Reloader::new(
+            args,
+            Box::new(|_| Err("filter is locked".to_string())),
+            config.clone(),
+            Limits::new(config.max_json_bytes, config.max_upload_bytes),
+            AdminKeys::default(),
+            #[cfg(feature = "deno")]
+            DenoApps::start(config).await.expect("Deno applications start"),
+        )
+    }
+
+    #[actix_web::test]
+    async fn failed_log_level_changes_keep_the_old_level() {
+        let reloader = reloader("log-level").await;
+
+        for _ in 0..2 {
+            let report = reloader.reload().await.expect("configuration is valid");
+            assert!(!report.reloaded.contains(&"server.log_level"));
+            assert_eq!(reloader.current().log_level, "info");
+        }
+    }
+}
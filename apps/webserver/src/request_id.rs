@@ -0,0 +1,78 @@
+use std::fmt;
+use std::future::{ready, Ready};
+
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use uuid::Uuid;
+
+/// Header carrying the request ID in both directions
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request ID that is accepted as is
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// ID correlating a request with the logs it produces, including the Deno application's
+///
+/// Taken from the `X-Request-Id` request header when the client sends a sensible one,
+/// and generated otherwise. It is echoed in the response's `X-Request-Id` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Pick the ID for an incoming request and store it in the request extensions
+    pub fn assign(req: &ServiceRequest) -> Self {
+        let request_id = Self::from_headers(req.headers());
+        req.extensions_mut().insert(request_id.clone());
+        request_id
+    }
+
+    /// Add the ID to the response headers
+    pub fn set_header(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.0) {
+            headers.insert(REQUEST_ID_HEADER, value);
+        }
+    }
+
+    /// The ID as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(|id| Self(id.to_string()))
+            .unwrap_or_else(Self::generate)
+    }
+
+    fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Requests that bypassed the middleware still get an ID for their logs
+        let request_id = req.extensions().get::<RequestId>().cloned().unwrap_or_else(Self::generate);
+        ready(Ok(request_id))
+    }
+}
+
+/// Accept short IDs made of characters that are safe to log and echo back
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use actix_web::http::Method;
+use serde::Deserialize;
+
+use crate::error::{Result, ServerError};
+
+/// Mount tables, which need the Deno runtime
+#[cfg(feature = "deno")]
+mod deno;
+
+#[cfg(feature = "deno")]
+pub use deno::{Mount, RouteTable, Routes};
+
+/// Route table file listing the Deno modules mounted on the server
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RouteFile {
+    mounts: Vec<MountConfig>,
+}
+
+/// A Deno module handler mounted on a path prefix
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    /// Name used in logs, the status endpoint and the mount's storage directory
+    pub name: String,
+
+    /// Path prefix served by the mount, such as `/api/summarize`
+    pub path: String,
+
+    /// HTTP methods accepted by the mount
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+
+    /// Entry module, relative to the route table file
+    pub module: PathBuf,
+
+    /// Function exported by the module (or defined on `globalThis`) that handles requests
+    pub handler: String,
+
+    /// Stream the handler's chunks as server-sent events
+    #[serde(default)]
+    pub stream: bool,
+
+    /// Number of isolates serving the mount
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["POST".to_string()]
+}
+
+fn default_workers() -> usize {
+    1
+}
+
+/// Load and validate a route table file
+///
+/// Module paths are resolved relative to the file's directory.
+pub fn load(path: &Path) -> Result<Vec<MountConfig>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ServerError::Config(format!("Failed to read route table {:?}: {}", path, e)))?;
+    let file: RouteFile = serde_json::from_str(&contents)
+        .map_err(|e| ServerError::Config(format!("Invalid route table {:?}: {}", path, e)))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut names = HashSet::new();
+    let mut prefixes = HashSet::new();
+    let mut mounts = Vec::with_capacity(file.mounts.len());
+
+    for mut mount in file.mounts {
+        let name = mount.name.clone();
+        let invalid = |reason: String| ServerError::Config(format!("Invalid mount {:?}: {}", name, reason));
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(invalid("name may only contain letters, digits, '-' and '_'".to_string()));
+        }
+        if !names.insert(name.clone()) {
+            return Err(invalid("duplicate name".to_string()));
+        }
+        if !mount.path.starts_with('/') {
+            return Err(invalid(format!("path {:?} must start with '/'", mount.path)));
+        }
+        mount.path = normalize_prefix(&mount.path);
+        if !prefixes.insert(mount.path.clone()) {
+            return Err(invalid(format!("path {:?} is already mounted", mount.path)));
+        }
+        if mount.methods.is_empty() {
+            return Err(invalid("no methods".to_string()));
+        }
+        for method in &mut mount.methods {
+            *method = method.to_ascii_uppercase();
+            Method::from_bytes(method.as_bytes()).map_err(|_| invalid(format!("invalid method {:?}", method)))?;
+        }
+        if !is_identifier(&mount.handler) {
+            return Err(invalid(format!("handler {:?} is not a function name", mount.handler)));
+        }
+        if mount.workers == 0 {
+            return Err(invalid("workers must be at least 1".to_string()));
+        }
+
+        mount.module = base.join(&mount.module);
+        if !mount.module.is_file() {
+            return Err(invalid(format!("module {:?} does not exist", mount.module)));
+        }
+
+        mounts.push(mount);
+    }
+
+    Ok(mounts)
+}
+
+/// Drop any trailing `/` so `/api/chat/` and `/api/chat` mount the same prefix
+fn normalize_prefix(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
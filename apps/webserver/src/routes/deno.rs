@@ -0,0 +1,125 @@
+use std::sync::{Arc, RwLock};
+
+use actix_web::http::Method;
+use tracing::info;
+
+use super::MountConfig;
+use crate::deno::DenoRuntime;
+use crate::error::Result;
+use crate::pool::{DenoPool, PoolStats};
+use crate::storage::StorageScope;
+use crate::watch;
+
+/// A mounted module and the isolates serving it
+pub struct Mount {
+    pub config: MountConfig,
+    pub pool: Arc<DenoPool>,
+    methods: Vec<Method>,
+}
+
+impl Mount {
+    /// Whether the mount serves `method` on `path`
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        self.methods.contains(method) && strip_prefix(path, &self.config.path).is_some()
+    }
+
+    /// Part of `path` below the mount's prefix, always starting with `/`
+    pub fn subpath<'a>(&self, path: &'a str) -> &'a str {
+        match strip_prefix(path, &self.config.path) {
+            Some("") | None => "/",
+            Some(rest) => rest,
+        }
+    }
+}
+
+/// Mounted modules, ordered so the longest matching prefix wins
+#[derive(Default)]
+pub struct RouteTable {
+    mounts: Vec<Arc<Mount>>,
+}
+
+impl RouteTable {
+    /// Start a worker pool for each mount
+    pub async fn start(runtime: &DenoRuntime, configs: &[MountConfig], watch_sources: bool) -> Result<Self> {
+        Self::default().restart(runtime, configs, watch_sources).await
+    }
+
+    /// Build a table for `configs`, keeping the running pool of every mount whose
+    /// configuration is unchanged and starting pools for the others
+    pub async fn restart(&self, runtime: &DenoRuntime, configs: &[MountConfig], watch_sources: bool) -> Result<Self> {
+        let mut mounts = Vec::with_capacity(configs.len());
+
+        for config in configs {
+            if let Some(mount) = self.mounts.iter().find(|mount| &mount.config == config) {
+                mounts.push(mount.clone());
+                continue;
+            }
+
+            info!("Mounting {} on {} with {} worker(s)", config.module.display(), config.path, config.workers);
+            let scope = StorageScope::Mount(config.name.clone());
+            let mount_runtime = Arc::new(runtime.for_module(config.module.clone(), scope));
+            let pool = DenoPool::new(mount_runtime.clone(), config.workers).await?;
+
+            if watch_sources {
+                watch::spawn(Arc::downgrade(&pool), mount_runtime.module_graph().clone())?;
+            }
+
+            mounts.push(Arc::new(Mount {
+                methods: config.methods.iter().filter_map(|m| Method::from_bytes(m.as_bytes()).ok()).collect(),
+                config: config.clone(),
+                pool,
+            }));
+        }
+
+        mounts.sort_by(|a, b| b.config.path.len().cmp(&a.config.path.len()));
+        Ok(Self { mounts })
+    }
+
+    /// Find the mount serving a request
+    pub fn find(&self, method: &Method, path: &str) -> Option<Arc<Mount>> {
+        self.mounts.iter().find(|mount| mount.matches(method, path)).cloned()
+    }
+
+    /// Find a mount by name
+    pub fn get(&self, name: &str) -> Option<Arc<Mount>> {
+        self.mounts.iter().find(|mount| mount.config.name == name).cloned()
+    }
+
+    /// Pool statistics of every mount, by name
+    pub fn stats(&self) -> Vec<(String, PoolStats)> {
+        self.mounts
+            .iter()
+            .map(|mount| (mount.config.name.clone(), mount.pool.stats()))
+            .collect()
+    }
+}
+
+/// The active route table, which can be swapped while the server runs
+#[derive(Clone, Default)]
+pub struct Routes(Arc<RwLock<Arc<RouteTable>>>);
+
+impl Routes {
+    /// Start serving `table`
+    pub fn new(table: RouteTable) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(table))))
+    }
+
+    /// The table in effect now
+    pub fn current(&self) -> Arc<RouteTable> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replace the table; requests already dispatched keep their mount
+    pub fn replace(&self, table: RouteTable) {
+        *self.0.write().unwrap() = Arc::new(table);
+    }
+}
+
+/// Strip a mount prefix at a segment boundary
+fn strip_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix == "/" {
+        return Some(path);
+    }
+    let rest = path.strip_prefix(prefix)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
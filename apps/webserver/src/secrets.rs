@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Result, ServerError};
+
+/// Length of the AES-GCM nonce prefixed to an encrypted secrets file
+const NONCE_LEN: usize = 12;
+
+/// Directory Docker and Kubernetes mount secret files into
+const DEFAULT_SECRETS_DIR: &str = "/run/secrets";
+
+/// A secret value that never shows up in `Debug` or `Display` output
+///
+/// Use `expose` only where the raw value is actually needed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wrap a raw value
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// The raw secret value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Serialized as `[redacted]`, so printed configuration never includes the value
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str("[redacted]")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Source of secrets by name
+pub trait SecretsProvider: Send + Sync {
+    /// Short name of the backend, used in log and error messages
+    fn name(&self) -> &'static str;
+
+    /// Look up a secret, returning `None` if this backend does not have it
+    fn get(&self, name: &str) -> Result<Option<Secret>>;
+}
+
+/// Secrets read from environment variables of the same name
+pub struct EnvSecrets;
+
+impl SecretsProvider for EnvSecrets {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Secret>> {
+        Ok(std::env::var(name).ok().map(Secret))
+    }
+}
+
+/// Secrets stored one per file, named after the secret, as mounted by Docker and Kubernetes
+pub struct FileSecrets {
+    dir: PathBuf,
+}
+
+impl FileSecrets {
+    /// Read secrets from files in `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl SecretsProvider for FileSecrets {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Secret>> {
+        // Secret names are plain identifiers, never paths
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Ok(None);
+        }
+
+        match fs::read_to_string(self.dir.join(name)) {
+            Ok(value) => Ok(Some(Secret(value.trim_end_matches(['\r', '\n']).to_string()))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(ServerError::Secret(format!("Failed to read secret {} from {:?}: {}", name, self.dir, err))),
+        }
+    }
+}
+
+/// Secrets from a JSON object of names to values, encrypted with AES-256-GCM
+///
+/// The file holds a 12-byte nonce followed by the ciphertext. It is decrypted once,
+/// when the provider is created. Files are produced with `--seal-secrets`.
+pub struct EncryptedFileSecrets {
+    secrets: HashMap<String, Secret>,
+}
+
+impl EncryptedFileSecrets {
+    /// Decrypt `path` with a base64-encoded 256-bit key
+    pub fn open(path: &Path, key: &Secret) -> Result<Self> {
+        let cipher = cipher(key)?;
+        let contents = fs::read(path)
+            .map_err(|e| ServerError::Secret(format!("Failed to read encrypted secrets file {:?}: {}", path, e)))?;
+
+        if contents.len() < NONCE_LEN {
+            return Err(ServerError::Secret(format!("Encrypted secrets file {:?} is truncated", path)));
+        }
+        let (nonce, ciphertext) = contents.split_at(NONCE_LEN);
+
+        // Decryption errors carry no detail, which keeps key material out of messages
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ServerError::Secret(format!("Failed to decrypt {:?}: wrong key or corrupted file", path)))?;
+        let secrets: HashMap<String, String> = serde_json::from_slice(&plaintext)
+            .map_err(|_| ServerError::Secret(format!("Decrypted {:?} is not a JSON object of strings", path)))?;
+
+        Ok(Self {
+            secrets: secrets.into_iter().map(|(name, value)| (name, Secret(value))).collect(),
+        })
+    }
+
+    /// Encrypt a JSON object of secrets into the file format read by `open`
+    pub fn seal(plaintext: &[u8], key: &Secret) -> Result<Vec<u8>> {
+        serde_json::from_slice::<HashMap<String, String>>(plaintext)
+            .map_err(|e| ServerError::Secret(format!("Secrets must be a JSON object of strings: {}", e)))?;
+
+        let cipher = cipher(key)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| ServerError::Secret("Failed to encrypt secrets".to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+}
+
+impl SecretsProvider for EncryptedFileSecrets {
+    fn name(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Secret>> {
+        Ok(self.secrets.get(name).cloned())
+    }
+}
+
+/// Providers consulted in order; the first one that has a secret wins
+pub struct ChainedSecrets(Vec<Box<dyn SecretsProvider>>);
+
+impl ChainedSecrets {
+    /// Consult `providers` in order
+    pub fn new(providers: Vec<Box<dyn SecretsProvider>>) -> Self {
+        Self(providers)
+    }
+
+    /// Build the provider chain from `SECRETS_PROVIDERS` and the backend settings
+    ///
+    /// `SECRETS_PROVIDERS` is a comma-separated list of `env`, `file` and
+    /// `encrypted-file`, defaulting to `env`. The file backend reads `SECRETS_DIR`
+    /// (default `/run/secrets`); the encrypted backend reads `SECRETS_FILE` with the
+    /// key from `SECRETS_KEY` or `SECRETS_KEY_FILE`.
+    pub fn from_env() -> Result<Self> {
+        let names = std::env::var("SECRETS_PROVIDERS").unwrap_or_else(|_| "env".to_string());
+        let mut providers: Vec<Box<dyn SecretsProvider>> = Vec::new();
+
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "env" => providers.push(Box::new(EnvSecrets)),
+                "file" => {
+                    let dir = std::env::var("SECRETS_DIR").unwrap_or_else(|_| DEFAULT_SECRETS_DIR.to_string());
+                    providers.push(Box::new(FileSecrets::new(dir)));
+                }
+                "encrypted-file" => {
+                    let path = std::env::var("SECRETS_FILE").map_err(|_| {
+                        ServerError::Secret("SECRETS_FILE must be set for the encrypted-file provider".to_string())
+                    })?;
+                    providers.push(Box::new(EncryptedFileSecrets::open(Path::new(&path), &key_from_env()?)?));
+                }
+                other => return Err(ServerError::Secret(format!("Unknown secrets provider: {}", other))),
+            }
+        }
+
+        Ok(Self(providers))
+    }
+
+    /// Load each named secret, skipping names no provider has
+    pub fn load(&self, names: &[String]) -> Result<HashMap<String, Secret>> {
+        let mut secrets = HashMap::new();
+        for name in names {
+            if let Some(secret) = self.get(name)? {
+                secrets.insert(name.clone(), secret);
+            }
+        }
+        Ok(secrets)
+    }
+}
+
+impl SecretsProvider for ChainedSecrets {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Secret>> {
+        for provider in &self.0 {
+            if let Some(secret) = provider.get(name)? {
+                tracing::debug!("Loaded secret {} from the {} provider", name, provider.name());
+                return Ok(Some(secret));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Key for encrypted secrets files, from `SECRETS_KEY` or the file named by `SECRETS_KEY_FILE`
+pub fn key_from_env() -> Result<Secret> {
+    if let Ok(key) = std::env::var("SECRETS_KEY") {
+        return Ok(Secret(key));
+    }
+
+    let path = std::env::var("SECRETS_KEY_FILE")
+        .map_err(|_| ServerError::Secret("SECRETS_KEY or SECRETS_KEY_FILE must be set".to_string()))?;
+    fs::read_to_string(&path)
+        .map(Secret)
+        .map_err(|e| ServerError::Secret(format!("Failed to read secrets key file {}: {}", path, e)))
+}
+
+fn cipher(key: &Secret) -> Result<Aes256Gcm> {
+    let key = BASE64
+        .decode(key.expose().trim())
+        .ok()
+        .filter(|key| key.len() == 32)
+        .ok_or_else(|| ServerError::Secret("Secrets key must be 32 bytes, base64-encoded".to_string()))?;
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> Secret {
+        Secret::new(BASE64.encode([byte; 32]))
+    }
+
+    /// Write `contents` to a file only this test uses
+    fn sealed_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("webserver-secrets-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn sealed_secrets_open_with_the_same_key() {
+        let sealed = EncryptedFileSecrets::seal(br#"{"API_TOKEN":"s3cret"}"#, &key(1)).unwrap();
+        let path = sealed_file("round-trip", &sealed);
+
+        let secrets = EncryptedFileSecrets::open(&path, &key(1)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(secrets.get("API_TOKEN").unwrap().unwrap().expose(), "s3cret");
+        assert!(secrets.get("OTHER").unwrap().is_none());
+    }
+
+    #[test]
+    fn opening_with_the_wrong_key_fails() {
+        let sealed = EncryptedFileSecrets::seal(br#"{"API_TOKEN":"s3cret"}"#, &key(1)).unwrap();
+        let path = sealed_file("wrong-key", &sealed);
+
+        let result = EncryptedFileSecrets::open(&path, &key(2));
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ServerError::Secret(message)) if message.contains("wrong key")));
+    }
+
+    #[test]
+    fn opening_tampered_ciphertext_fails() {
+        let mut sealed = EncryptedFileSecrets::seal(br#"{"API_TOKEN":"s3cret"}"#, &key(1)).unwrap();
+        sealed[NONCE_LEN] ^= 0x01;
+        let path = sealed_file("tampered", &sealed);
+
+        let result = EncryptedFileSecrets::open(&path, &key(1));
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ServerError::Secret(message)) if message.contains("corrupted")));
+    }
+
+    #[test]
+    fn secret_values_are_never_printed() {
+        let secret = Secret::new("s3cret");
+
+        assert!(!format!("{:?}", secret).contains("s3cret"));
+        assert!(!format!("{}", secret).contains("s3cret"));
+        assert!(!serde_json::to_string(&secret).unwrap().contains("s3cret"));
+        assert_eq!(secret.expose(), "s3cret");
+    }
+}
@@ -1,138 +1,333 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use actix_files::Files;
 use actix_web::{
     web, App, HttpRequest, HttpResponse, HttpServer, Responder, Result as ActixResult, Error as ActixError,
-    http::StatusCode, middleware::{Logger, Compress},
+    http::{header, StatusCode}, middleware::{Logger, Compress},
 };
-use actix_web::dev::Server as ActixServer;
+use actix_web::dev::{Server as ActixServer, ServerHandle as ActixServerHandle, Service};
 use actix_web::rt::signal;
 use actix_web::web::Data;
 use actix_web_lab::sse::{self, Sse, ChannelStream, SseSender};
 use futures::StreamExt;
-use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
-use tracing::{info, error, debug};
-
+use tokio::sync::mpsc;
+use tracing::{info, warn, error, debug};
+
+use crate::admin::{self, AdminKeys};
+use crate::client_identity::{self, ClientIdentity};
+#[cfg(feature = "deno")]
+use crate::apps::{self, DenoApps};
+use crate::backend::{AiBackend, CopilotRequest, CopilotResponse, CopilotStreamChunk, HttpBackend, MockBackend};
+#[cfg(feature = "deno")]
+use crate::backend::DenoBackend;
 use crate::error::{ServerError, Result};
-use crate::config::ServerConfig;
-use crate::deno::DenoRuntime;
-use deno_runtime::worker::MainWorker;
+use crate::config::{AiBackendConfig, Args, ServerConfig};
+use crate::limits::Limits;
+use crate::reload::{LogFilter, Reloader};
+use crate::request_id::RequestId;
+use crate::shutdown::{Shutdown, SHUTDOWN_MESSAGE};
+use crate::tls::{self, CertResolver};
+
+/// Time open streams get after the grace period to send their final chunk and close
+const FINAL_CHUNK_SECS: u64 = 5;
 
 /// Server state shared across all routes
 #[derive(Clone)]
 pub struct AppState {
-    config: Arc<ServerConfig>,
-    deno_runtime: Arc<DenoRuntime>,
-    deno_worker: Arc<Mutex<Option<MainWorker>>>,
-}
-
-/// Request payload for the copilotkit endpoint
-#[derive(Debug, Deserialize)]
-pub struct CopilotRequest {
-    message: String,
-    #[serde(default)]
-    context: serde_json::Value,
-    #[serde(default)]
-    stream: bool,
-}
-
-/// Response payload for the copilotkit endpoint
-#[derive(Debug, Serialize)]
-pub struct CopilotResponse {
-    message: String,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    actions: Vec<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
-}
-
-/// Streaming chunk for the copilotkit endpoint
-#[derive(Debug, Serialize)]
-pub struct CopilotStreamChunk {
-    chunk: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    done: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+    pub(crate) config: Arc<ServerConfig>,
+    pub(crate) backend: Arc<dyn AiBackend>,
+    pub(crate) admin_keys: AdminKeys,
+    pub(crate) reloader: Option<Arc<Reloader>>,
+    pub(crate) shutdown: Shutdown,
+    #[cfg(feature = "deno")]
+    pub(crate) deno: DenoApps,
 }
 
 /// The main server struct
 pub struct Server {
     config: Arc<ServerConfig>,
+    reload: Option<(Args, LogFilter)>,
 }
 
 impl Server {
     /// Create a new server instance
     pub fn new(config: ServerConfig) -> Self {
         let config = Arc::new(config);
-        Self { config }
+        Self { config, reload: None }
+    }
+
+    /// Reload the configuration layered from `args` on SIGHUP and `POST /api/admin/reload`
+    ///
+    /// `log_filter` is called with the new log level when it changes.
+    pub fn with_reload(mut self, args: Args, log_filter: LogFilter) -> Self {
+        self.reload = Some((args, log_filter));
+        self
     }
 
     /// Start the server and run until shutdown signal
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(self) -> Result<()> {
         let addr = self.config.addr;
         let static_dir = self.config.static_dir.clone();
 
-        // Initialize Deno runtime
-        let deno_runtime = DenoRuntime::new(self.config.clone());
-        let deno_runtime = Arc::new(deno_runtime);
+        // Start the Deno applications: mounted modules, deployed bundles and, when it
+        // answers copilot requests or exists, the application at DENO_APP_PATH
+        #[cfg(feature = "deno")]
+        let mut deno = DenoApps::start(self.config.clone()).await?;
+        #[cfg(feature = "deno")]
+        let main_pool = if self.config.ai_backend == AiBackendConfig::Deno || self.config.deno_app_path.exists() {
+            Some(deno.start_main(self.config.deno_workers).await?)
+        } else {
+            None
+        };
+
+        // Choose the source of copilot answers
+        let backend: Arc<dyn AiBackend> = match &self.config.ai_backend {
+            #[cfg(feature = "deno")]
+            AiBackendConfig::Deno => match main_pool {
+                Some(pool) => Arc::new(DenoBackend::new(pool)),
+                None => return Err(ServerError::Config("The Deno application is not running".to_string())),
+            },
+            #[cfg(not(feature = "deno"))]
+            AiBackendConfig::Deno => {
+                return Err(ServerError::Config("The deno AI backend requires the deno feature".to_string()));
+            }
+            AiBackendConfig::Http { url } => {
+                let backend = HttpBackend::new(url.clone())?;
+                if !backend.probe().await {
+                    warn!("AI service at {} is not reachable yet", url);
+                }
+                Arc::new(backend)
+            }
+            AiBackendConfig::Mock => Arc::new(MockBackend::default()),
+        };
+        info!("Answering copilot requests with the {} backend", backend.name());
+        if self.config.ai_backend == AiBackendConfig::Deno && !self.config.secrets.contains_key("OPENAI_API_KEY") {
+            warn!("OPENAI_API_KEY is not available from any secrets provider");
+        }
 
-        // Initialize Deno worker
-        info!("Initializing Deno runtime...");
-        let deno_worker = deno_runtime.init().await?;
-        let deno_worker = Arc::new(Mutex::new(Some(deno_worker)));
+        let admin_keys = AdminKeys::new(self.config.admin_keys.clone());
+        if !admin_keys.is_enabled() {
+            info!("Admin API disabled; set ADMIN_API_KEYS to enable it");
+        }
 
-        info!("Deno runtime initialized successfully");
+        // Body limits are shared with the reloader, which may change them
+        let limits = Limits::new(self.config.max_json_bytes, self.config.max_upload_bytes);
+        let reloader = self.reload.map(|(args, log_filter)| {
+            Arc::new(Reloader::new(
+                args,
+                log_filter,
+                self.config.clone(),
+                limits.clone(),
+                admin_keys.clone(),
+                #[cfg(feature = "deno")]
+                deno.clone(),
+            ))
+        });
+        #[cfg(unix)]
+        if let Some(reloader) = &reloader {
+            spawn_reload_on_hangup(reloader.clone())?;
+        }
 
         // Create shared state
         let state = AppState {
             config: self.config.clone(),
-            deno_runtime: deno_runtime.clone(),
-            deno_worker: deno_worker.clone(),
+            backend,
+            admin_keys,
+            reloader,
+            shutdown: Shutdown::new(),
+            #[cfg(feature = "deno")]
+            deno,
+        };
+
+        // Serve HTTPS with a certificate that is reloaded when its files change
+        let tls_config = match &self.config.tls {
+            Some(tls) => {
+                let resolver = Arc::new(CertResolver::load(&tls.cert_file, &tls.key_file)?);
+                tls::watch(resolver.clone())?;
+                Some(tls::server_config(resolver, tls.client_auth.as_ref())?)
+            }
+            None => None,
         };
+        let hsts = self.config.tls.as_ref().and_then(|tls| tls.hsts_max_age).map(tls::hsts_header);
 
         // Start Actix-web server
-        info!("Starting server on {}", addr);
+        let scheme = if tls_config.is_some() { "https" } else { "http" };
+        info!("Starting server on {}://{}", scheme, addr);
+        if let Some(client_auth) = self.config.tls.as_ref().and_then(|tls| tls.client_auth.as_ref()) {
+            let mode = if client_auth.required { "required" } else { "optional" };
+            info!("Client certificates from {:?} are {}", client_auth.ca_file, mode);
+        }
         info!("Serving static files from {:?}", static_dir);
 
+        #[cfg(feature = "deno")]
+        let routes = state.deno.routes.clone();
+        let shutdown = state.shutdown.clone();
+        let shutdown_grace = self.config.shutdown_grace;
         let state_data = Data::new(state);
 
-        HttpServer::new(move || {
-            App::new()
+        let http_server = HttpServer::new(move || {
+            let limits = limits.clone();
+            let hsts = hsts.clone();
+            let app = App::new()
                 .app_data(state_data.clone())
-                .wrap(Logger::default())
+                // Body sizes are capped by `Limits`, which can change while the server runs
+                .app_data(web::JsonConfig::default().limit(usize::MAX))
+                .app_data(web::PayloadConfig::new(usize::MAX))
+                .wrap_fn(move |req, srv| srv.call(limits.limit_body(req)))
+                // Make the verified client certificate of the connection available to handlers
+                .wrap_fn(|req, srv| {
+                    ClientIdentity::assign(&req);
+                    srv.call(req)
+                })
+                // Tag each request with an ID that is echoed back and attached to its logs
+                .wrap_fn(|req, srv| {
+                    let request_id = RequestId::assign(&req);
+                    let response = srv.call(req);
+                    async move {
+                        let mut response = response.await?;
+                        request_id.set_header(response.headers_mut());
+                        Ok(response)
+                    }
+                })
+                // Tell browsers to keep using HTTPS
+                .wrap_fn(move |req, srv| {
+                    let response = srv.call(req);
+                    let hsts = hsts.clone();
+                    async move {
+                        let mut response = response.await?;
+                        if let Some(hsts) = hsts {
+                            response.headers_mut().insert(header::STRICT_TRANSPORT_SECURITY, hsts);
+                        }
+                        Ok(response)
+                    }
+                })
+                .wrap(Logger::new("%a \"%r\" %s %b %T %{x-request-id}o"))
                 .wrap(Compress::default())
-                // API routes
-                .service(
-                    web::resource("/api/copilotkit")
-                        .route(web::post().to(handle_copilot_request))
-                )
-                .service(
-                    web::resource("/api/copilotkit/stream")
-                        .route(web::post().to(handle_copilot_stream))
-                )
-                // Serve static files
-                .service(
-                    Files::new("/", &static_dir)
-                        .index_file("index.html")
-                        .prefer_utf8(true)
-                        .use_last_modified(true)
-                        .default_handler(web::to(spa_fallback))
-                )
+                .configure(configure_api)
+                .configure(admin::configure);
+
+            // Functions, bundles and mounted Deno modules
+            #[cfg(feature = "deno")]
+            let app = app.configure(|cfg| apps::configure(cfg, &routes));
+
+            // Serve static files
+            app.service(
+                Files::new("/", &static_dir)
+                    .index_file("index.html")
+                    .prefer_utf8(true)
+                    .use_last_modified(true)
+                    .default_handler(web::to(spa_fallback))
+            )
         })
-        .bind(addr)?
-        .run()
-        .await
-        .map_err(ServerError::from)?;
+        .on_connect(client_identity::on_connect);
+        let http_server = match tls_config {
+            Some(tls_config) => http_server.bind_rustls_0_23(addr, tls_config)?,
+            None => http_server.bind(addr)?,
+        };
+        let server: ActixServer = http_server
+            // SIGTERM and SIGINT are handled by `stop_on_signal`, which drains open streams first
+            .disable_signals()
+            .shutdown_timeout(shutdown_grace.as_secs() + FINAL_CHUNK_SECS)
+            .run();
+
+        // Redirect plain HTTP to the HTTPS listener
+        let redirect = match self.config.tls.as_ref().and_then(|tls| tls.redirect_addr) {
+            Some(redirect_addr) => {
+                info!("Redirecting http://{} to HTTPS", redirect_addr);
+                let redirect = tls::redirect_server(redirect_addr, addr.port())?;
+                let handle = redirect.handle();
+                actix_web::rt::spawn(redirect);
+                Some(handle)
+            }
+            None => None,
+        };
 
+        actix_web::rt::spawn(stop_on_signal(server.handle(), redirect, shutdown, shutdown_grace));
+        server.await.map_err(ServerError::from)?;
+
+        // The Deno workers are torn down as the last references to their pools are dropped
         info!("Server shutdown complete");
         Ok(())
     }
 }
 
+/// Register the copilotkit and status routes
+pub(crate) fn configure_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/api/copilotkit")
+            .route(web::post().to(handle_copilot_request))
+    )
+    .service(
+        web::resource("/api/copilotkit/stream")
+            .route(web::post().to(handle_copilot_stream))
+    )
+    .service(
+        web::resource("/api/status")
+            .route(web::get().to(handle_status))
+    );
+}
+
+/// Stop the server gracefully on SIGTERM or SIGINT
+///
+/// The listeners close at once, while open streams and in-flight calls get `grace` to
+/// finish. Streams still open after that send a final chunk and end; any other request
+/// still running is cut off `FINAL_CHUNK_SECS` later.
+async fn stop_on_signal(
+    handle: ActixServerHandle,
+    redirect: Option<ActixServerHandle>,
+    shutdown: Shutdown,
+    grace: Duration,
+) {
+    if let Err(err) = wait_for_stop_signal().await {
+        error!("Failed to listen for shutdown signals: {}", err);
+        return;
+    }
+    info!("Shutting down; waiting up to {:?} for open streams and in-flight calls", grace);
+
+    shutdown.drain();
+    if let Some(redirect) = redirect {
+        actix_web::rt::spawn(redirect.stop(true));
+    }
+    let stopped = handle.stop(true);
+    if tokio::time::timeout(grace, stopped).await.is_err() {
+        warn!("Shutdown grace period expired; ending open streams");
+        shutdown.expire();
+    }
+}
+
+/// Wait for SIGTERM or SIGINT
+async fn wait_for_stop_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
+    signal::ctrl_c().await
+}
+
+/// Reload the configuration each time the process receives SIGHUP
+#[cfg(unix)]
+fn spawn_reload_on_hangup(reloader: Arc<Reloader>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    actix_web::rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading configuration");
+            // Failures are logged by the reloader, and the server keeps its configuration
+            let _ = reloader.reload().await;
+        }
+    });
+    Ok(())
+}
+
 /// Handler for SPA fallback - serves index.html for all non-file routes
 async fn spa_fallback(req: HttpRequest, data: Data<AppState>) -> ActixResult<impl Responder> {
     let path = data.config.static_dir.join("index.html");
@@ -153,73 +348,65 @@ async fn spa_fallback(req: HttpRequest, data: Data<AppState>) -> ActixResult<imp
     }
 }
 
+/// Handler for the server status endpoint
+async fn handle_status(data: Data<AppState>) -> ActixResult<impl Responder> {
+    let backend = data.backend.status();
+    let (ready, size) = (backend.ready, backend.size);
+    let mut body = serde_json::json!({
+        "backend": data.backend.name(),
+        data.backend.name(): backend.details,
+    });
+
+    // Mounted modules count towards the status like the backend
+    #[cfg(feature = "deno")]
+    let (ready, size) = {
+        let apps = data.deno.status();
+        if let (Some(body), serde_json::Value::Object(details)) = (body.as_object_mut(), apps.details) {
+            body.extend(details);
+        }
+        (ready + apps.ready, size + apps.size)
+    };
+
+    // Report degraded service while some backend units or Deno workers are restarting
+    let (status, code) = if ready == size {
+        ("ok", StatusCode::OK)
+    } else if ready > 0 {
+        ("degraded", StatusCode::OK)
+    } else {
+        ("unavailable", StatusCode::SERVICE_UNAVAILABLE)
+    };
+    body["status"] = serde_json::json!(status);
+
+    Ok(HttpResponse::build(code).json(body))
+}
+
 /// Handler for copilotkit API requests
 async fn handle_copilot_request(
+    http_req: HttpRequest,
     data: Data<AppState>,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
     req: web::Json<CopilotRequest>,
-) -> ActixResult<impl Responder> {
+) -> ActixResult<HttpResponse> {
     let request = req.into_inner();
     debug!("Received copilotkit request: {:?}", request);
 
     // If streaming is requested, redirect to the streaming endpoint
     if request.stream {
-        return handle_copilot_stream(data, web::Json(request)).await;
+        return handle_copilot_stream(http_req, data, request_id, client, web::Json(request)).await;
     }
 
-    // Get a lock on the Deno worker
-    let mut worker_lock = data.deno_worker.lock().await;
-
-    // Check if worker is available
-    let worker = match worker_lock.as_mut() {
-        Some(worker) => worker,
-        None => {
-            error!("Deno worker not initialized");
-            return Ok(HttpResponse::InternalServerError().json(CopilotResponse {
-                message: "Internal server error".to_string(),
-                actions: vec![],
-                error: Some("Deno runtime not initialized".to_string()),
-            }));
-        }
-    };
-
-    // Prepare the request JSON for the Deno function
-    let request_json = match serde_json::to_string(&request) {
-        Ok(json) => json,
-        Err(err) => {
-            error!("Failed to serialize request: {}", err);
-            return Ok(HttpResponse::InternalServerError().json(CopilotResponse {
-                message: "Internal server error".to_string(),
-                actions: vec![],
-                error: Some(format!("Failed to serialize request: {}", err)),
-            }));
-        }
-    };
-
-    // Call the Deno function
-    let result = match data.deno_runtime.execute_function(worker, "handleCopilotRequest", &format!("'{}'", request_json)).await {
-        Ok(result) => result,
-        Err(err) => {
-            error!("Failed to execute Deno function: {}", err);
-            return Ok(HttpResponse::InternalServerError().json(CopilotResponse {
-                message: "Internal server error".to_string(),
-                actions: vec![],
-                error: Some(format!("Failed to execute Deno function: {}", err)),
-            }));
-        }
-    };
-
-    // Parse the response
-    match serde_json::from_str::<CopilotResponse>(&result) {
+    match data.backend.copilot(request_id.as_str(), client.as_ref(), &request).await {
         Ok(response) => {
             debug!("Copilotkit response: {:?}", response);
             Ok(HttpResponse::Ok().json(response))
         },
         Err(err) => {
-            error!("Failed to parse Deno response: {}", err);
-            Ok(HttpResponse::InternalServerError().json(CopilotResponse {
-                message: "Internal server error".to_string(),
+            error!(request_id = %request_id, "{} backend failed: {}", data.backend.name(), err);
+            Ok(HttpResponse::build(error_status(&err)).json(CopilotResponse {
+                message: error_message(&err).to_string(),
                 actions: vec![],
-                error: Some(format!("Failed to parse Deno response: {}", err)),
+                error: Some(format!("Failed to answer copilot request: {}", err)),
             }))
         }
     }
@@ -227,106 +414,173 @@ async fn handle_copilot_request(
 
 /// Handler for streaming copilotkit API requests
 async fn handle_copilot_stream(
+    http_req: HttpRequest,
     data: Data<AppState>,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
     req: web::Json<CopilotRequest>,
-) -> ActixResult<impl Responder> {
+) -> ActixResult<HttpResponse> {
     let request = req.into_inner();
     debug!("Received streaming copilotkit request: {:?}", request);
 
+    if data.shutdown.is_draining() {
+        return Ok(HttpResponse::ServiceUnavailable().json(CopilotResponse {
+            message: SHUTDOWN_MESSAGE.to_string(),
+            actions: vec![],
+            error: Some(SHUTDOWN_MESSAGE.to_string()),
+        }));
+    }
+
     let (tx, rx) = sse::channel(100);
 
     // Clone state for the async task
     let state_clone = data.get_ref().clone();
     actix_web::rt::spawn(async move {
-        stream_copilot_response(state_clone, request, tx).await;
+        stream_copilot_response(state_clone, request_id, client, request, tx).await;
     });
 
-    Ok(Sse::new(ChannelStream::new(rx)).keep_alive(sse::KeepAlive::new().interval(std::time::Duration::from_secs(15))))
+    let response = Sse::new(ChannelStream::new(rx))
+        .keep_alive(sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .respond_to(&http_req)
+        .map_into_boxed_body();
+    Ok(response)
 }
 
 /// Stream the copilot response
 async fn stream_copilot_response(
     state: AppState,
+    request_id: RequestId,
+    client: Option<ClientIdentity>,
     request: CopilotRequest,
     tx: SseSender,
 ) {
-    // Get a lock on the Deno worker
-    let mut worker_lock = state.deno_worker.lock().await;
-
-    // Check if worker is available
-    let worker = match worker_lock.as_mut() {
-        Some(worker) => worker,
-        None => {
-            error!("Deno worker not initialized for streaming");
-            let error_chunk = CopilotStreamChunk {
-                chunk: "Internal server error".to_string(),
-                done: Some(true),
-                error: Some("Deno runtime not initialized".to_string()),
+    // Chunks flow from the backend through this channel as they are produced. A capacity of
+    // one means the backend waits until the previous chunk has been handed to the SSE channel.
+    let (chunk_tx, chunk_rx) = mpsc::channel::<serde_json::Value>(1);
+
+    // Forward each chunk to the client as soon as it arrives. The receiver is moved in so it
+    // is dropped when forwarding stops, which tells the backend the client has gone away.
+    let forward = async {
+        let mut chunk_rx = chunk_rx;
+        let mut done_sent = false;
+        while let Some(value) = chunk_rx.recv().await {
+            let chunk = match serde_json::from_value::<CopilotStreamChunk>(value) {
+                Ok(chunk) => chunk,
+                Err(err) => {
+                    error!("Failed to parse streaming copilot chunk: {}", err);
+                    continue;
+                }
             };
-            if let Ok(json) = serde_json::to_string(&error_chunk) {
-                let _ = tx.send(sse::Data::new(json)).await;
+            let Ok(json) = serde_json::to_string(&chunk) else {
+                continue;
+            };
+            if tx.send(sse::Data::new(json)).await.is_err() {
+                debug!("Client disconnected from copilotkit stream");
+                break;
+            }
+            if chunk.done.unwrap_or(false) {
+                done_sent = true;
+                break;
             }
-            return;
         }
+        done_sent
     };
 
-    // Prepare the request JSON for the Deno function
-    let request_json = match serde_json::to_string(&request) {
-        Ok(json) => json,
-        Err(err) => {
-            error!("Failed to serialize streaming request: {}", err);
-            let error_chunk = CopilotStreamChunk {
-                chunk: "Internal server error".to_string(),
-                done: Some(true),
-                error: Some(format!("Failed to serialize request: {}", err)),
-            };
-            if let Ok(json) = serde_json::to_string(&error_chunk) {
-                let _ = tx.send(sse::Data::new(json)).await;
-            }
-            return;
+    let call = state.backend.copilot_stream(request_id.as_str(), client.as_ref(), &request, chunk_tx);
+
+    // Streams still open when the shutdown grace period ends are told why they stop
+    let outcome = tokio::select! {
+        outcome = async { futures::join!(call, forward) } => Some(outcome),
+        () = state.shutdown.expired() => None,
+    };
+    let Some((result, done_sent)) = outcome else {
+        let chunk = CopilotStreamChunk {
+            chunk: String::new(),
+            done: Some(true),
+            error: Some(SHUTDOWN_MESSAGE.to_string()),
+        };
+        if let Ok(json) = serde_json::to_string(&chunk) {
+            let _ = tx.send(sse::Data::new(json)).await;
         }
+        return;
     };
 
-    // Call the Deno function for streaming
-    match state.deno_runtime.execute_function(worker, "handleCopilotStreamRequest", &format!("'{}'", request_json)).await {
-        Ok(result) => {
-            // The result should be a JSON array of chunks
-            match serde_json::from_str::<Vec<CopilotStreamChunk>>(&result) {
-                Ok(chunks) => {
-                    for chunk in chunks {
-                        if let Ok(json) = serde_json::to_string(&chunk) {
-                            if tx.send(sse::Data::new(json)).await.is_err() {
-                                break;
-                            }
-                            if chunk.done.unwrap_or(false) {
-                                break;
-                            }
-                        }
-                    }
-                },
-                Err(err) => {
-                    error!("Failed to parse streaming Deno response: {}", err);
-                    let error_chunk = CopilotStreamChunk {
-                        chunk: "Internal server error".to_string(),
-                        done: Some(true),
-                        error: Some(format!("Failed to parse Deno response: {}", err)),
-                    };
-                    if let Ok(json) = serde_json::to_string(&error_chunk) {
-                        let _ = tx.send(sse::Data::new(json)).await;
-                    }
-                }
-            }
+    let final_chunk = match result {
+        Ok(()) if done_sent => return,
+        Ok(()) => CopilotStreamChunk {
+            chunk: String::new(),
+            done: Some(true),
+            error: None,
         },
         Err(err) => {
-            error!("Failed to execute streaming Deno function: {}", err);
-            let error_chunk = CopilotStreamChunk {
-                chunk: "Internal server error".to_string(),
+            error!(request_id = %request_id, "{} backend failed to stream: {}", state.backend.name(), err);
+            CopilotStreamChunk {
+                chunk: error_message(&err).to_string(),
                 done: Some(true),
-                error: Some(format!("Failed to execute Deno function: {}", err)),
-            };
-            if let Ok(json) = serde_json::to_string(&error_chunk) {
-                let _ = tx.send(sse::Data::new(json)).await;
+                error: Some(format!("Failed to answer copilot request: {}", err)),
             }
         }
+    };
+
+    if let Ok(json) = serde_json::to_string(&final_chunk) {
+        let _ = tx.send(sse::Data::new(json)).await;
+    }
+}
+
+/// HTTP status for a failed Deno call or AI backend request
+pub(crate) fn error_status(err: &ServerError) -> StatusCode {
+    match err {
+        ServerError::Backend(_) => StatusCode::BAD_GATEWAY,
+        ServerError::DenoPermission(_) => StatusCode::FORBIDDEN,
+        ServerError::DenoTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        ServerError::DenoUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Parse a JSON request body, treating an empty body as `T::default()` (`null` for a `Value`)
+///
+/// Invalid JSON yields the `400 Bad Request` response to send instead.
+pub(crate) fn json_body<T>(body: &web::Bytes) -> std::result::Result<T, HttpResponse>
+where
+    T: serde::de::DeserializeOwned + Default,
+{
+    if body.is_empty() {
+        return Ok(T::default());
     }
-}
\ No newline at end of file
+    serde_json::from_slice(body).map_err(|err| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid JSON body: {}", err),
+        }))
+    })
+}
+
+/// Message shown to the client for a failed Deno call or AI backend request, matching `error_status`
+fn error_message(err: &ServerError) -> &'static str {
+    match err {
+        ServerError::DenoPermission(_) => "Permission denied",
+        _ => "Internal server error",
+    }
+}
+
+/// Shared state for handler tests, keeping data in a directory named after the test
+#[cfg(test)]
+pub(crate) async fn test_state(backend: Arc<dyn AiBackend>, name: &str) -> Data<AppState> {
+    let dir = std::env::temp_dir().join(format!("webserver-{}-{}", std::process::id(), name));
+    let mut settings = crate::settings::Settings::defaults();
+    settings.static_files.dir = Some(std::env::temp_dir());
+    settings.server.data_dir = Some(dir.clone());
+    settings.deno.cache_dir = Some(dir.join("cache"));
+    settings.ai.backend = Some("mock".to_string());
+    let config = Arc::new(ServerConfig::from_settings(&settings, None).expect("test settings are valid"));
+
+    Data::new(AppState {
+        config: config.clone(),
+        backend,
+        admin_keys: AdminKeys::default(),
+        reloader: None,
+        shutdown: Shutdown::new(),
+        #[cfg(feature = "deno")]
+        deno: DenoApps::start(config).await.expect("Deno applications start"),
+    })
+}
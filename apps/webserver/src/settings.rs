@@ -0,0 +1,678 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Args, DenoInspect};
+use crate::secrets::Secret;
+
+/// Prefix of the environment variables overriding configuration file settings
+pub const ENV_PREFIX: &str = "WEBSERVER_";
+
+/// Environment variable naming the configuration file when `--config` is not given
+const CONFIG_FILE_VAR: &str = "WEBSERVER_CONFIG";
+
+/// Address to bind the server to
+const DEFAULT_ADDR: &str = "127.0.0.1:3000";
+
+/// Address of the Node.js AI service for the http backend
+const DEFAULT_AI_SERVICE_URL: &str = "http://127.0.0.1:3001";
+
+/// AI backend used unless configured otherwise
+#[cfg(feature = "deno")]
+const DEFAULT_AI_BACKEND: &str = "deno";
+#[cfg(not(feature = "deno"))]
+const DEFAULT_AI_BACKEND: &str = "http";
+
+/// Largest JSON body accepted by the API, in bytes
+const DEFAULT_MAX_JSON_BYTES: usize = 2 * 1024 * 1024;
+
+/// Largest bundle accepted by the admin API, in bytes
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 4 * 1024 * 1024;
+
+/// Longest call timeout a bundle may ask for, in seconds
+const DEFAULT_BUNDLE_MAX_TIMEOUT_SECS: u64 = 300;
+
+/// Largest heap limit a bundle may ask for, in megabytes
+const DEFAULT_BUNDLE_MAX_HEAP_MB: usize = 1024;
+
+/// Server settings before validation, layered from defaults, a configuration file,
+/// `WEBSERVER_*` environment variables and command line arguments
+///
+/// Every value is optional so that each layer only overrides what it sets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub tls: TlsSettings,
+    pub static_files: StaticFilesSettings,
+    pub ai: AiSettings,
+    pub deno: DenoSettings,
+    pub auth: AuthSettings,
+    pub limits: LimitsSettings,
+}
+
+/// `[server]`: listener, threads, logging and data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Address to bind the server to
+    pub addr: Option<String>,
+
+    /// Number of worker threads; 0 uses the number of CPU cores
+    pub worker_threads: Option<usize>,
+
+    /// Log level (trace, debug, info, warn, error)
+    pub log_level: Option<String>,
+
+    /// Directory for persistent data: deployed bundles and per-application storage
+    pub data_dir: Option<PathBuf>,
+
+    /// Seconds open streams and in-flight Deno calls get to finish after SIGTERM or SIGINT
+    pub shutdown_grace_secs: Option<u64>,
+}
+
+/// `[tls]`: HTTPS listener
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    /// PEM certificate chain; HTTPS is served on `server.addr` when set with `key_file`
+    pub cert_file: Option<PathBuf>,
+
+    /// PEM private key for the certificate
+    pub key_file: Option<PathBuf>,
+
+    /// Address of a plain HTTP listener that redirects every request to HTTPS
+    pub redirect_addr: Option<String>,
+
+    /// `max-age` of the Strict-Transport-Security header in seconds; 0 omits the header
+    pub hsts_max_age_secs: Option<u64>,
+
+    /// PEM bundle of the CAs that issue client certificates; enables mutual TLS
+    pub client_ca_file: Option<PathBuf>,
+
+    /// Refuse TLS connections without a client certificate, instead of serving them anonymously
+    pub client_auth_required: Option<bool>,
+
+    /// Patterns for the subject or common name of accepted client certificates; `*` matches anything
+    pub client_allowed_subjects: Option<Vec<String>>,
+
+    /// Patterns for the DNS, URI, email or IP subject alternative names of accepted client certificates
+    pub client_allowed_sans: Option<Vec<String>>,
+}
+
+/// `[static_files]`: the React/Vite build output
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StaticFilesSettings {
+    /// Directory the files are served from
+    pub dir: Option<PathBuf>,
+}
+
+/// `[ai]`: source of copilot answers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiSettings {
+    /// deno, http or mock
+    pub backend: Option<String>,
+
+    /// Base URL of the Node.js AI service for the http backend
+    pub service_url: Option<String>,
+}
+
+/// `[deno]`: the embedded Deno runtime
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DenoSettings {
+    /// Path to the Deno application
+    pub app_path: Option<PathBuf>,
+
+    /// Number of isolates in the worker pool; 0 uses the number of worker threads
+    pub workers: Option<usize>,
+
+    /// Directory for transpiled module output
+    pub cache_dir: Option<PathBuf>,
+
+    /// Directory holding vendored remote, npm and jsr modules
+    pub vendor_dir: Option<PathBuf>,
+
+    /// Boot workers from a cached V8 startup snapshot
+    pub snapshot: Option<bool>,
+
+    /// Reload the application when its modules change
+    pub watch: Option<bool>,
+
+    /// Enable Deno's unstable APIs, which include `Deno.openKv()`
+    pub kv: Option<bool>,
+
+    /// JSON route table mounting additional Deno modules
+    pub routes: Option<PathBuf>,
+
+    /// Directory storing function bundles deployed through the admin API
+    pub bundles_dir: Option<PathBuf>,
+
+    /// Names of the secrets exposed through `Webserver.secrets`
+    pub secrets: Option<Vec<String>>,
+
+    /// Permissions granted to the application
+    pub permissions: PermissionSettings,
+
+    /// Chrome DevTools inspector, only set from the command line
+    #[serde(skip)]
+    pub inspect: Option<DenoInspect>,
+}
+
+/// `[deno.permissions]`: allow lists following Deno's `--allow-*` flags
+///
+/// `["*"]` allows everything and an empty list denies the permission.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermissionSettings {
+    pub allow_read: Option<Vec<String>>,
+    pub allow_write: Option<Vec<String>>,
+    pub allow_env: Option<Vec<String>>,
+    pub allow_net: Option<Vec<String>>,
+    pub allow_run: Option<Vec<String>>,
+    pub allow_ffi: Option<Vec<String>>,
+}
+
+/// `[auth]`: credentials of the admin API
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    /// Bearer tokens accepted by the admin API, in addition to `ADMIN_API_KEYS`
+    pub admin_keys: Option<Vec<Secret>>,
+}
+
+/// `[limits]`: resource limits
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSettings {
+    /// Seconds a Deno call may run before it is terminated; 0 disables the limit
+    pub deno_call_timeout_secs: Option<u64>,
+
+    /// Seconds a streaming Deno call may run before it is terminated; 0 disables the limit
+    pub deno_stream_timeout_secs: Option<u64>,
+
+    /// V8 heap limit for each Deno isolate in megabytes; 0 uses the V8 default
+    pub deno_max_heap_mb: Option<usize>,
+
+    /// Largest JSON body accepted by the API, in bytes
+    pub max_json_bytes: Option<usize>,
+
+    /// Largest bundle accepted by the admin API, in bytes
+    pub max_upload_bytes: Option<usize>,
+
+    /// Longest `timeout_secs` a bundle upload may set
+    pub bundle_max_timeout_secs: Option<u64>,
+
+    /// Largest `max_heap_mb` a bundle upload may set
+    pub bundle_max_heap_mb: Option<usize>,
+}
+
+impl Settings {
+    /// Layer defaults, the configuration file, the environment and `args`, in increasing precedence
+    ///
+    /// Errors of every layer are collected, so a broken file and a malformed variable are
+    /// reported together.
+    pub fn load(args: &Args) -> Result<Self, Vec<String>> {
+        // Load environment variables from .env file
+        dotenv::dotenv().ok();
+
+        let mut errors = Vec::new();
+        let mut settings = Self::defaults();
+
+        if let Some(path) = Self::config_file(args) {
+            match Self::from_file(&path) {
+                Ok(file) => settings = settings.merge(file),
+                Err(err) => errors.push(err),
+            }
+        }
+        settings = settings.merge(Self::from_env(&mut errors));
+        settings = settings.merge(Self::from_args(args));
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(settings.with_derived_defaults())
+    }
+
+    /// Configuration file named by `--config` or `WEBSERVER_CONFIG`
+    pub fn config_file(args: &Args) -> Option<PathBuf> {
+        args.config.clone().or_else(|| env::var_os(CONFIG_FILE_VAR).map(PathBuf::from))
+    }
+
+    /// Built-in defaults
+    pub fn defaults() -> Self {
+        Self {
+            server: ServerSettings {
+                addr: Some(DEFAULT_ADDR.to_string()),
+                worker_threads: Some(0),
+                log_level: Some("info".to_string()),
+                data_dir: Some(PathBuf::from(".data")),
+                shutdown_grace_secs: Some(30),
+            },
+            tls: TlsSettings {
+                cert_file: None,
+                key_file: None,
+                redirect_addr: None,
+                hsts_max_age_secs: Some(0),
+                client_ca_file: None,
+                client_auth_required: Some(false),
+                client_allowed_subjects: Some(vec![]),
+                client_allowed_sans: Some(vec![]),
+            },
+            static_files: StaticFilesSettings {
+                dir: Some(PathBuf::from("dist")),
+            },
+            ai: AiSettings {
+                backend: Some(DEFAULT_AI_BACKEND.to_string()),
+                service_url: Some(DEFAULT_AI_SERVICE_URL.to_string()),
+            },
+            deno: DenoSettings {
+                app_path: Some(PathBuf::from("../apps/ai-service/main.ts")),
+                workers: Some(0),
+                cache_dir: Some(PathBuf::from(".cache/deno")),
+                vendor_dir: None,
+                snapshot: Some(false),
+                watch: Some(false),
+                kv: Some(false),
+                routes: None,
+                bundles_dir: None,
+                secrets: Some(vec![]),
+                permissions: PermissionSettings {
+                    allow_env: Some(vec!["OPENAI_API_KEY".to_string()]),
+                    allow_net: Some(vec!["*".to_string()]),
+                    allow_write: Some(vec![]),
+                    allow_run: Some(vec![]),
+                    allow_ffi: Some(vec![]),
+                    allow_read: None,
+                },
+                inspect: None,
+            },
+            auth: AuthSettings {
+                admin_keys: Some(vec![]),
+            },
+            limits: LimitsSettings {
+                deno_call_timeout_secs: Some(120),
+                deno_stream_timeout_secs: Some(3600),
+                deno_max_heap_mb: Some(0),
+                max_json_bytes: Some(DEFAULT_MAX_JSON_BYTES),
+                max_upload_bytes: Some(DEFAULT_MAX_UPLOAD_BYTES),
+                bundle_max_timeout_secs: Some(DEFAULT_BUNDLE_MAX_TIMEOUT_SECS),
+                bundle_max_heap_mb: Some(DEFAULT_BUNDLE_MAX_HEAP_MB),
+            },
+        }
+    }
+
+    /// Read a TOML file, or a YAML file if its extension is `.yaml` or `.yml`
+    ///
+    /// Relative paths in the file are resolved from the current directory, like those on the
+    /// command line.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read configuration file {:?}: {}", path, e))?;
+
+        let yaml = matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml" | "yml"));
+        if yaml {
+            serde_yaml::from_str(&contents).map_err(|e| format!("Invalid configuration file {:?}: {}", path, e))
+        } else {
+            toml::from_str(&contents).map_err(|e| format!("Invalid configuration file {:?}: {}", path, e))
+        }
+    }
+
+    /// Read `WEBSERVER_<SECTION>_<KEY>` variables, such as `WEBSERVER_DENO_WORKERS`
+    ///
+    /// Lists are comma-separated. The older unprefixed variables (`DENO_APP_PATH`,
+    /// `DENO_SECRETS`, `DENO_ALLOW_*` and `AI_SERVICE_URL`) are still read, with lower
+    /// precedence than their prefixed forms.
+    pub fn from_env(errors: &mut Vec<String>) -> Self {
+        let mut vars = EnvLayer { errors };
+
+        Self {
+            server: ServerSettings {
+                addr: vars.string("SERVER_ADDR"),
+                worker_threads: vars.parse("SERVER_WORKER_THREADS"),
+                log_level: vars.string("SERVER_LOG_LEVEL"),
+                data_dir: vars.path("SERVER_DATA_DIR"),
+                shutdown_grace_secs: vars.parse("SERVER_SHUTDOWN_GRACE_SECS"),
+            },
+            tls: TlsSettings {
+                cert_file: vars.path("TLS_CERT_FILE"),
+                key_file: vars.path("TLS_KEY_FILE"),
+                redirect_addr: vars.string("TLS_REDIRECT_ADDR"),
+                hsts_max_age_secs: vars.parse("TLS_HSTS_MAX_AGE_SECS"),
+                client_ca_file: vars.path("TLS_CLIENT_CA_FILE"),
+                client_auth_required: vars.parse("TLS_CLIENT_AUTH_REQUIRED"),
+                client_allowed_subjects: vars.list("TLS_CLIENT_ALLOWED_SUBJECTS"),
+                client_allowed_sans: vars.list("TLS_CLIENT_ALLOWED_SANS"),
+            },
+            static_files: StaticFilesSettings {
+                dir: vars.path("STATIC_FILES_DIR"),
+            },
+            ai: AiSettings {
+                backend: vars.string("AI_BACKEND"),
+                service_url: vars.string("AI_SERVICE_URL").or_else(|| env::var("AI_SERVICE_URL").ok()),
+            },
+            deno: DenoSettings {
+                app_path: vars.path("DENO_APP_PATH").or_else(|| env::var_os("DENO_APP_PATH").map(PathBuf::from)),
+                workers: vars.parse("DENO_WORKERS"),
+                cache_dir: vars.path("DENO_CACHE_DIR"),
+                vendor_dir: vars.path("DENO_VENDOR_DIR"),
+                snapshot: vars.parse("DENO_SNAPSHOT"),
+                watch: vars.parse("DENO_WATCH"),
+                kv: vars.parse("DENO_KV"),
+                routes: vars.path("DENO_ROUTES"),
+                bundles_dir: vars.path("DENO_BUNDLES_DIR"),
+                secrets: vars.list("DENO_SECRETS").or_else(|| env::var("DENO_SECRETS").ok().map(|value| split_list(&value))),
+                permissions: PermissionSettings {
+                    allow_read: vars.permission("DENO_ALLOW_READ"),
+                    allow_write: vars.permission("DENO_ALLOW_WRITE"),
+                    allow_env: vars.permission("DENO_ALLOW_ENV"),
+                    allow_net: vars.permission("DENO_ALLOW_NET"),
+                    allow_run: vars.permission("DENO_ALLOW_RUN"),
+                    allow_ffi: vars.permission("DENO_ALLOW_FFI"),
+                },
+                inspect: None,
+            },
+            auth: AuthSettings {
+                admin_keys: vars.list("AUTH_ADMIN_KEYS").map(|keys| keys.into_iter().map(Secret::new).collect()),
+            },
+            limits: LimitsSettings {
+                deno_call_timeout_secs: vars.parse("LIMITS_DENO_CALL_TIMEOUT_SECS"),
+                deno_stream_timeout_secs: vars.parse("LIMITS_DENO_STREAM_TIMEOUT_SECS"),
+                deno_max_heap_mb: vars.parse("LIMITS_DENO_MAX_HEAP_MB"),
+                max_json_bytes: vars.parse("LIMITS_MAX_JSON_BYTES"),
+                max_upload_bytes: vars.parse("LIMITS_MAX_UPLOAD_BYTES"),
+                bundle_max_timeout_secs: vars.parse("LIMITS_BUNDLE_MAX_TIMEOUT_SECS"),
+                bundle_max_heap_mb: vars.parse("LIMITS_BUNDLE_MAX_HEAP_MB"),
+            },
+        }
+    }
+
+    /// Settings given on the command line
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            server: ServerSettings {
+                addr: args.addr.clone(),
+                worker_threads: args.worker_threads,
+                log_level: args.log_level.clone(),
+                data_dir: args.data_dir.clone(),
+                shutdown_grace_secs: args.shutdown_grace,
+            },
+            tls: TlsSettings {
+                cert_file: args.tls_cert.clone(),
+                key_file: args.tls_key.clone(),
+                redirect_addr: args.tls_redirect_addr.clone(),
+                hsts_max_age_secs: args.hsts_max_age,
+                client_ca_file: args.tls_client_ca.clone(),
+                client_auth_required: args.tls_client_auth_required.then_some(true),
+                client_allowed_subjects: None,
+                client_allowed_sans: None,
+            },
+            static_files: StaticFilesSettings {
+                dir: args.static_dir.clone(),
+            },
+            ai: AiSettings {
+                backend: args.ai_backend.clone(),
+                service_url: args.ai_service_url.clone(),
+            },
+            deno: DenoSettings {
+                app_path: None,
+                workers: args.deno_workers,
+                cache_dir: args.deno_cache_dir.clone(),
+                vendor_dir: args.deno_vendor_dir.clone(),
+                snapshot: args.deno_snapshot.then_some(true),
+                watch: args.watch.then_some(true),
+                kv: args.deno_kv.then_some(true),
+                routes: args.routes.clone(),
+                bundles_dir: args.bundles_dir.clone(),
+                secrets: None,
+                permissions: PermissionSettings::default(),
+                inspect: DenoInspect::from_args(args),
+            },
+            auth: AuthSettings::default(),
+            limits: LimitsSettings {
+                deno_call_timeout_secs: args.deno_call_timeout,
+                deno_stream_timeout_secs: args.deno_stream_timeout,
+                deno_max_heap_mb: args.deno_max_heap_mb,
+                max_json_bytes: None,
+                max_upload_bytes: None,
+                bundle_max_timeout_secs: None,
+                bundle_max_heap_mb: None,
+            },
+        }
+    }
+
+    /// Override these settings with every value `other` sets
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            server: ServerSettings {
+                addr: other.server.addr.or(self.server.addr),
+                worker_threads: other.server.worker_threads.or(self.server.worker_threads),
+                log_level: other.server.log_level.or(self.server.log_level),
+                data_dir: other.server.data_dir.or(self.server.data_dir),
+                shutdown_grace_secs: other.server.shutdown_grace_secs.or(self.server.shutdown_grace_secs),
+            },
+            tls: TlsSettings {
+                cert_file: other.tls.cert_file.or(self.tls.cert_file),
+                key_file: other.tls.key_file.or(self.tls.key_file),
+                redirect_addr: other.tls.redirect_addr.or(self.tls.redirect_addr),
+                hsts_max_age_secs: other.tls.hsts_max_age_secs.or(self.tls.hsts_max_age_secs),
+                client_ca_file: other.tls.client_ca_file.or(self.tls.client_ca_file),
+                client_auth_required: other.tls.client_auth_required.or(self.tls.client_auth_required),
+                client_allowed_subjects: other.tls.client_allowed_subjects.or(self.tls.client_allowed_subjects),
+                client_allowed_sans: other.tls.client_allowed_sans.or(self.tls.client_allowed_sans),
+            },
+            static_files: StaticFilesSettings {
+                dir: other.static_files.dir.or(self.static_files.dir),
+            },
+            ai: AiSettings {
+                backend: other.ai.backend.or(self.ai.backend),
+                service_url: other.ai.service_url.or(self.ai.service_url),
+            },
+            deno: DenoSettings {
+                app_path: other.deno.app_path.or(self.deno.app_path),
+                workers: other.deno.workers.or(self.deno.workers),
+                cache_dir: other.deno.cache_dir.or(self.deno.cache_dir),
+                vendor_dir: other.deno.vendor_dir.or(self.deno.vendor_dir),
+                snapshot: other.deno.snapshot.or(self.deno.snapshot),
+                watch: other.deno.watch.or(self.deno.watch),
+                kv: other.deno.kv.or(self.deno.kv),
+                routes: other.deno.routes.or(self.deno.routes),
+                bundles_dir: other.deno.bundles_dir.or(self.deno.bundles_dir),
+                secrets: other.deno.secrets.or(self.deno.secrets),
+                permissions: PermissionSettings {
+                    allow_read: other.deno.permissions.allow_read.or(self.deno.permissions.allow_read),
+                    allow_write: other.deno.permissions.allow_write.or(self.deno.permissions.allow_write),
+                    allow_env: other.deno.permissions.allow_env.or(self.deno.permissions.allow_env),
+                    allow_net: other.deno.permissions.allow_net.or(self.deno.permissions.allow_net),
+                    allow_run: other.deno.permissions.allow_run.or(self.deno.permissions.allow_run),
+                    allow_ffi: other.deno.permissions.allow_ffi.or(self.deno.permissions.allow_ffi),
+                },
+                inspect: other.deno.inspect.or(self.deno.inspect),
+            },
+            auth: AuthSettings {
+                admin_keys: other.auth.admin_keys.or(self.auth.admin_keys),
+            },
+            limits: LimitsSettings {
+                deno_call_timeout_secs: other.limits.deno_call_timeout_secs.or(self.limits.deno_call_timeout_secs),
+                deno_stream_timeout_secs: other.limits.deno_stream_timeout_secs.or(self.limits.deno_stream_timeout_secs),
+                deno_max_heap_mb: other.limits.deno_max_heap_mb.or(self.limits.deno_max_heap_mb),
+                max_json_bytes: other.limits.max_json_bytes.or(self.limits.max_json_bytes),
+                max_upload_bytes: other.limits.max_upload_bytes.or(self.limits.max_upload_bytes),
+                bundle_max_timeout_secs: other.limits.bundle_max_timeout_secs.or(self.limits.bundle_max_timeout_secs),
+                bundle_max_heap_mb: other.limits.bundle_max_heap_mb.or(self.limits.bundle_max_heap_mb),
+            },
+        }
+    }
+
+    /// Fill in defaults that depend on other settings
+    fn with_derived_defaults(mut self) -> Self {
+        // Bundles are kept with the rest of the persistent data
+        if self.deno.bundles_dir.is_none() {
+            self.deno.bundles_dir = self.server.data_dir.as_ref().map(|dir| dir.join("bundles"));
+        }
+
+        // The application may read its own directory
+        if self.deno.permissions.allow_read.is_none() {
+            let app_dir = self
+                .deno
+                .app_path
+                .as_deref()
+                .and_then(Path::parent)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| PathBuf::from("."));
+            self.deno.permissions.allow_read = Some(vec![app_dir.to_string_lossy().into_owned()]);
+        }
+
+        self
+    }
+
+    /// The settings as TOML, with secrets redacted
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap_or_else(|e| format!("# Failed to render settings: {}\n", e))
+    }
+}
+
+/// Reads prefixed environment variables, collecting malformed values
+struct EnvLayer<'a> {
+    errors: &'a mut Vec<String>,
+}
+
+impl EnvLayer<'_> {
+    fn string(&self, key: &str) -> Option<String> {
+        env::var(format!("{}{}", ENV_PREFIX, key)).ok()
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        env::var_os(format!("{}{}", ENV_PREFIX, key)).map(PathBuf::from)
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str) -> Option<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        let value = self.string(key)?;
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.errors.push(format!("Invalid {}{}={:?}: {}", ENV_PREFIX, key, value, err));
+                None
+            }
+        }
+    }
+
+    fn list(&self, key: &str) -> Option<Vec<String>> {
+        self.string(key).map(|value| split_list(&value))
+    }
+
+    /// A `DENO_ALLOW_*` list: `*` allows everything, `none` or an empty value denies
+    fn permission(&self, key: &str) -> Option<Vec<String>> {
+        let value = self.string(key).or_else(|| env::var(key).ok())?;
+        Some(match value.trim() {
+            "" | "none" => vec![],
+            list => split_list(list),
+        })
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::config::permission_list;
+
+    /// Write `contents` to a configuration file only this test uses
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("webserver-settings-{}-{}.toml", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let path = config_file(
+            "precedence",
+            r#"
+            [server]
+            addr = "0.0.0.0:8080"
+            worker_threads = 2
+            log_level = "debug"
+            "#,
+        );
+        env::set_var("WEBSERVER_SERVER_WORKER_THREADS", "4");
+        env::set_var("WEBSERVER_SERVER_LOG_LEVEL", "warn");
+        let args = Args::parse_from(["webserver", "--config", path.to_str().unwrap(), "--log-level", "error"]);
+
+        let settings = Settings::load(&args);
+        env::remove_var("WEBSERVER_SERVER_WORKER_THREADS");
+        env::remove_var("WEBSERVER_SERVER_LOG_LEVEL");
+        fs::remove_file(&path).unwrap();
+        let settings = settings.unwrap();
+
+        // Defaults < file < environment < command line
+        assert_eq!(settings.server.shutdown_grace_secs, Some(30));
+        assert_eq!(settings.server.addr.as_deref(), Some("0.0.0.0:8080"));
+        assert_eq!(settings.server.worker_threads, Some(4));
+        assert_eq!(settings.server.log_level.as_deref(), Some("error"));
+    }
+
+    #[test]
+    fn permission_variables_deny_when_empty_or_none() {
+        let mut errors = Vec::new();
+        let vars = EnvLayer { errors: &mut errors };
+
+        env::set_var("WEBSERVER_TEST_ALLOW_EMPTY", "");
+        env::set_var("WEBSERVER_TEST_ALLOW_NONE", " none ");
+        assert_eq!(vars.permission("TEST_ALLOW_EMPTY"), Some(vec![]));
+        assert_eq!(vars.permission("TEST_ALLOW_NONE"), Some(vec![]));
+        assert_eq!(vars.permission("TEST_ALLOW_UNSET"), None);
+
+        assert_eq!(permission_list(&vars.permission("TEST_ALLOW_EMPTY")), None);
+        assert_eq!(permission_list(&vars.permission("TEST_ALLOW_UNSET")), None);
+        env::remove_var("WEBSERVER_TEST_ALLOW_EMPTY");
+        env::remove_var("WEBSERVER_TEST_ALLOW_NONE");
+    }
+
+    #[test]
+    fn permission_variables_allow_everything_with_a_star() {
+        let mut errors = Vec::new();
+        let vars = EnvLayer { errors: &mut errors };
+
+        env::set_var("WEBSERVER_TEST_ALLOW_ALL", "*");
+        env::set_var("WEBSERVER_TEST_ALLOW_SOME", "api.example.com, ,localhost");
+        // The unprefixed form is read when the prefixed one is missing
+        env::set_var("TEST_ALLOW_LEGACY", "*");
+        assert_eq!(permission_list(&vars.permission("TEST_ALLOW_ALL")), Some(vec![]));
+        assert_eq!(permission_list(&vars.permission("TEST_ALLOW_LEGACY")), Some(vec![]));
+        assert_eq!(
+            vars.permission("TEST_ALLOW_SOME"),
+            Some(vec!["api.example.com".to_string(), "localhost".to_string()])
+        );
+        env::remove_var("WEBSERVER_TEST_ALLOW_ALL");
+        env::remove_var("WEBSERVER_TEST_ALLOW_SOME");
+        env::remove_var("TEST_ALLOW_LEGACY");
+    }
+
+    #[test]
+    fn rendered_settings_redact_admin_keys() {
+        let mut settings = Settings::defaults();
+        settings.auth.admin_keys = Some(vec![Secret::new("hunter2-admin-key")]);
+
+        let toml = settings.to_toml();
+        assert!(!toml.contains("hunter2-admin-key"));
+        assert!(toml.contains("[redacted]"));
+    }
+}
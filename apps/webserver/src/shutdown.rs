@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Told to clients whose stream is ended by a shutdown
+pub const SHUTDOWN_MESSAGE: &str = "Server shutting down";
+
+/// Progress of a graceful shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Running,
+    /// No new connections are accepted; open responses may still finish
+    Draining,
+    /// The grace period is over; open streams must end now
+    Expired,
+}
+
+/// Shutdown state shared with the handlers of long-lived responses
+///
+/// When the server is asked to stop, it starts draining: streams keep running for the
+/// grace period, and new streams are refused. Streams still open when the grace period
+/// expires send a final chunk and end, so clients learn why the stream stopped.
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<Phase>>);
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::channel(Phase::Running).0))
+    }
+
+    /// Stop taking new streams, letting open ones finish
+    pub fn drain(&self) {
+        self.advance(Phase::Draining);
+    }
+
+    /// End the grace period, telling open streams to finish now
+    pub fn expire(&self) {
+        self.advance(Phase::Expired);
+    }
+
+    /// Whether the server is shutting down
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow() >= Phase::Draining
+    }
+
+    /// Resolve once the grace period has expired
+    pub async fn expired(&self) {
+        let mut phase = self.0.subscribe();
+        // The sender lives as long as `self`, so this only fails once the phase is final
+        let _ = phase.wait_for(|phase| *phase == Phase::Expired).await;
+    }
+
+    fn advance(&self, next: Phase) {
+        self.0.send_if_modified(|phase| {
+            let advanced = next > *phase;
+            if advanced {
+                *phase = next;
+            }
+            advanced
+        });
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
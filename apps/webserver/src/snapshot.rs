@@ -0,0 +1,365 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use deno_core::{v8, Extension, JsRuntime, JsRuntimeForSnapshot, ModuleSpecifier, RuntimeOptions, Snapshot};
+use deno_runtime::deno_broadcast_channel::InMemoryBroadcastChannel;
+use deno_runtime::deno_fs::RealFs;
+use deno_runtime::deno_web::BlobStore;
+use deno_runtime::ops::{TestingFeaturesEnabled, UnstableChecker};
+use deno_runtime::permissions::{Permissions, PermissionsContainer};
+use deno_runtime::BootstrapOptions;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+
+use crate::config::DenoPermissions;
+use crate::deno::{bootstrap_options, expose_exports, op_names, permissions_options, WEBSERVER_API_SCRIPT};
+use crate::error::{Result, ServerError};
+use crate::loader::{ModuleGraph, SourceMapStore, TsModuleLoader};
+use crate::ops::{webserver_ops, HostServices, SecretStore};
+use crate::vendor::Vendor;
+
+/// Identifies the runtime a snapshot was made with; snapshots from other builds are stale
+const SNAPSHOT_RUNTIME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"), "/deno_runtime-0.124");
+
+/// Describes what a snapshot contains, written next to it
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotManifest {
+    runtime: String,
+    main_module: String,
+    /// SHA-256 of every module evaluated into the snapshot
+    modules: BTreeMap<PathBuf, String>,
+}
+
+/// V8 startup snapshot of the Deno runtime with the application module evaluated
+///
+/// Stored in the cache directory with a manifest of the module hashes it was built
+/// from. A snapshot is only used while every one of those modules is unchanged;
+/// otherwise workers boot normally and a fresh snapshot is built in the background.
+pub struct SnapshotCache {
+    snapshot_path: PathBuf,
+    manifest_path: PathBuf,
+    building: Arc<AtomicBool>,
+}
+
+/// Inputs needed to build a snapshot off the worker threads
+#[derive(Clone)]
+pub struct SnapshotSource {
+    pub main_module: ModuleSpecifier,
+    pub cache_dir: PathBuf,
+    pub vendor_dir: Option<PathBuf>,
+    /// Sandbox of the workers; the top-level code runs in it without environment access
+    pub permissions: DenoPermissions,
+    pub unstable: bool,
+    /// Services of the workers; secrets and environment variables are withheld so none is written to disk
+    pub services: HostServices,
+    /// Ops of a worker booted without the snapshot, which the snapshot must register identically
+    pub ops: Vec<String>,
+}
+
+/// Clears the building flag when the build thread ends, even by panicking
+struct BuildingGuard(Arc<AtomicBool>);
+
+impl Drop for BuildingGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl SnapshotCache {
+    /// Create a snapshot cache in `cache_dir` for the application rooted at `main_module`
+    pub fn new(cache_dir: &Path, main_module: &Path) -> Self {
+        // Each mounted module gets its own snapshot
+        let id = format!("{:x}", Sha256::digest(main_module.to_string_lossy().as_bytes()));
+        let stem = format!("snapshot-{}", &id[..16]);
+        
+        Self {
+            snapshot_path: cache_dir.join(format!("{}.bin", stem)),
+            manifest_path: cache_dir.join(format!("{}.json", stem)),
+            building: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Load the snapshot if it exists and matches the current sources, recording its modules in `graph`
+    pub fn load(&self, main_module: &ModuleSpecifier, graph: &ModuleGraph) -> Option<Snapshot> {
+        let manifest: SnapshotManifest = serde_json::from_slice(&fs::read(&self.manifest_path).ok()?).ok()?;
+
+        if manifest.runtime != SNAPSHOT_RUNTIME || manifest.main_module != main_module.as_str() {
+            debug!("Ignoring snapshot built for a different runtime or main module");
+            return None;
+        }
+
+        for (path, expected) in &manifest.modules {
+            if hash_file(path).as_deref() != Some(expected.as_str()) {
+                debug!("Ignoring stale snapshot: {:?} has changed", path);
+                return None;
+            }
+        }
+
+        let bytes = fs::read(&self.snapshot_path).ok()?;
+
+        // The loader never sees these modules, so the watcher learns about them here
+        for path in manifest.modules.into_keys() {
+            graph.insert(path);
+        }
+
+        debug!("Loaded startup snapshot ({} bytes)", bytes.len());
+        Some(Snapshot::Boxed(bytes.into_boxed_slice()))
+    }
+
+    /// Build a fresh snapshot on a background thread unless one is already being built
+    pub fn rebuild_in_background(&self, source: SnapshotSource) {
+        if self.building.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let snapshot_path = self.snapshot_path.clone();
+        let manifest_path = self.manifest_path.clone();
+        // Also dropped with the closure if the thread cannot be started
+        let building = BuildingGuard(self.building.clone());
+
+        let spawned = std::thread::Builder::new()
+            .name("deno-snapshot".to_string())
+            .spawn(move || {
+                let _building = building;
+                match build(&source).and_then(|(snapshot, manifest)| write(&snapshot_path, &snapshot, &manifest_path, &manifest)) {
+                    Ok(()) => info!("Wrote Deno startup snapshot to {:?}", snapshot_path),
+                    Err(err) => warn!("Failed to build Deno startup snapshot: {}", err),
+                }
+            });
+
+        if let Err(err) = spawned {
+            error!("Failed to start snapshot thread: {}", err);
+        }
+    }
+}
+
+/// Evaluate the main module on top of the runtime snapshot and serialize the result
+///
+/// The runtime is bootstrapped like a worker's, with the same `Webserver` API and permissions,
+/// so top-level code behaves as it would in a worker booted without the snapshot. Secrets
+/// and environment variables are withheld, since anything read would be written to disk.
+fn build(source: &SnapshotSource) -> Result<(Box<[u8]>, SnapshotManifest)> {
+    let graph = ModuleGraph::default();
+    let mut runtime = runtime(source, &graph)?;
+
+    let ops = op_names(&mut runtime)?;
+    if ops != source.ops {
+        return Err(ServerError::DenoRuntime(format!(
+            "Snapshot registers {} ops where workers register {}, or in another order; snapshot_extensions is out of date",
+            ops.len(),
+            source.ops.len(),
+        )));
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    rt.block_on(async {
+        let id = runtime.load_main_module(&source.main_module, None).await?;
+        let evaluated = runtime.mod_evaluate(id);
+        runtime.run_event_loop(false).await?;
+        evaluated.await??;
+        expose_exports(&mut runtime, id)
+    })
+    .map_err(|e| ServerError::DenoRuntime(format!("Failed to evaluate main module for snapshot: {}", e)))?;
+
+    let modules = graph
+        .files()
+        .into_iter()
+        .filter_map(|path| hash_file(&path).map(|hash| (path, hash)))
+        .collect();
+
+    let manifest = SnapshotManifest {
+        runtime: SNAPSHOT_RUNTIME.to_string(),
+        main_module: source.main_module.to_string(),
+        modules,
+    };
+
+    Ok((runtime.snapshot().to_vec().into_boxed_slice(), manifest))
+}
+
+/// Runtime for `build`, bootstrapped like a worker's, with the `Webserver` API installed
+/// and loaded modules recorded in `graph`
+fn runtime(source: &SnapshotSource, graph: &ModuleGraph) -> Result<JsRuntimeForSnapshot> {
+    let vendor = source.vendor_dir.as_deref().map(Vendor::load).transpose()?;
+    let permissions = DenoPermissions {
+        allow_env: None,
+        ..source.permissions.clone()
+    };
+    let permissions = Permissions::from_options(&permissions_options(&permissions))
+        .map_err(|e| ServerError::Config(format!("Invalid Deno permissions: {}", e)))?;
+    let permissions = PermissionsContainer::new(permissions);
+    let loader = TsModuleLoader::new(source.cache_dir.clone(), SourceMapStore::default(), vendor, graph.clone())
+        .with_permissions(permissions.clone());
+    let services = HostServices {
+        secrets: SecretStore::withheld(),
+        ..source.services.clone()
+    };
+
+    let mut runtime = JsRuntimeForSnapshot::new(
+        RuntimeOptions {
+            startup_snapshot: Some(deno_runtime::js::deno_isolate_init()),
+            extensions: snapshot_extensions(&source.main_module, services),
+            module_loader: Some(Rc::new(loader)),
+            ..Default::default()
+        },
+        Default::default(),
+    );
+
+    // State `MainWorker` adds through extensions that register no ops
+    {
+        let op_state = runtime.op_state();
+        let mut op_state = op_state.borrow_mut();
+        op_state.put(permissions);
+        op_state.put(UnstableChecker { unstable: source.unstable });
+        op_state.put(TestingFeaturesEnabled(false));
+    }
+
+    bootstrap(&mut runtime, &bootstrap_options(false, source.unstable))?;
+    runtime
+        .execute_script_static("[webserver_api]", WEBSERVER_API_SCRIPT)
+        .map_err(|e| ServerError::DenoExecution(format!("Failed to install Webserver API: {}", e)))?;
+    Ok(runtime)
+}
+
+/// Extensions registered by `MainWorker`, in the same order, so the snapshot's op table matches
+///
+/// Options that only matter at run time are left inert here; `MainWorker` supplies
+/// the real ones when it boots from the snapshot. `build` checks the resulting ops
+/// against those of a worker, so a runtime upgrade that changes the list fails the build.
+fn snapshot_extensions(main_module: &ModuleSpecifier, services: HostServices) -> Vec<Extension> {
+    use deno_runtime::*;
+
+    let fs = Arc::new(RealFs);
+    let mut extensions = vec![
+        deno_webidl::deno_webidl::init_ops(),
+        deno_console::deno_console::init_ops(),
+        deno_url::deno_url::init_ops(),
+        deno_web::deno_web::init_ops::<PermissionsContainer>(Arc::new(BlobStore::default()), None),
+        deno_fetch::deno_fetch::init_ops::<PermissionsContainer>(Default::default()),
+        deno_cache::deno_cache::init_ops::<deno_cache::SqliteBackedCache>(None),
+        deno_websocket::deno_websocket::init_ops::<PermissionsContainer>(String::new(), None, None),
+        deno_webstorage::deno_webstorage::init_ops(None),
+        deno_crypto::deno_crypto::init_ops(None),
+        deno_broadcast_channel::deno_broadcast_channel::init_ops(InMemoryBroadcastChannel::default(), false),
+        deno_ffi::deno_ffi::init_ops::<PermissionsContainer>(false),
+        deno_net::deno_net::init_ops::<PermissionsContainer>(None, false, None),
+        deno_tls::deno_tls::init_ops(),
+        deno_kv::deno_kv::init_ops(deno_kv::sqlite::SqliteDbHandler::<PermissionsContainer>::new(None), false),
+        deno_napi::deno_napi::init_ops::<PermissionsContainer>(),
+        deno_http::deno_http::init_ops::<deno_http::DefaultHttpPropertyExtractor>(),
+        deno_io::deno_io::init_ops(Default::default()),
+        deno_fs::deno_fs::init_ops::<PermissionsContainer>(false, fs.clone()),
+        deno_node::deno_node::init_ops::<PermissionsContainer>(None, fs),
+        ops::runtime::deno_runtime::init_ops(main_module.clone()),
+        ops::worker_host::deno_worker_host::init_ops(Arc::new(|_| unreachable!()), None),
+        ops::fs_events::deno_fs_events::init_ops(),
+        ops::os::deno_os::init_ops(Default::default()),
+        ops::permissions::deno_permissions::init_ops(),
+        ops::process::deno_process::init_ops(),
+        ops::signal::deno_signal::init_ops(),
+        ops::tty::deno_tty::init_ops(),
+        ops::http::deno_http_runtime::init_ops(),
+    ];
+
+    extensions.push(webserver_ops::init_ops_and_esm(services));
+    extensions
+}
+
+/// Run the runtime's `bootstrapMainRuntime` as `MainWorker` does, then leave a no-op in its place
+///
+/// `MainWorker` bootstraps every runtime it creates, including those restored from this
+/// snapshot, and the runtime refuses to be bootstrapped twice.
+fn bootstrap(runtime: &mut JsRuntime, options: &BootstrapOptions) -> Result<()> {
+    runtime.op_state().borrow_mut().put(options.clone());
+
+    {
+        let scope = &mut runtime.handle_scope();
+        let global = scope.get_current_context().global(scope);
+        let namespace = v8::String::new(scope, "bootstrap")
+            .and_then(|key| global.get(scope, key.into()))
+            .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+            .ok_or_else(|| ServerError::DenoV8("The runtime has no bootstrap namespace".to_string()))?;
+        let main_runtime = v8::String::new(scope, "mainRuntime")
+            .and_then(|key| namespace.get(scope, key.into()))
+            .and_then(|value| v8::Local::<v8::Function>::try_from(value).ok())
+            .ok_or_else(|| ServerError::DenoV8("The runtime has no bootstrap.mainRuntime".to_string()))?;
+
+        let args = options.as_v8(scope);
+        let scope = &mut v8::TryCatch::new(scope);
+        let recv = v8::undefined(scope).into();
+        if main_runtime.call(scope, recv, &[args.into()]).is_none() {
+            let message = scope
+                .exception()
+                .map(|exception| exception.to_rust_string_lossy(scope))
+                .unwrap_or_else(|| "unknown exception".to_string());
+            return Err(ServerError::DenoRuntime(format!("Failed to bootstrap the runtime: {}", message)));
+        }
+    }
+
+    runtime
+        .execute_script_static("[webserver_bootstrap]", "globalThis.bootstrap = { mainRuntime() {}, workerRuntime() {} };")
+        .map_err(|e| ServerError::DenoExecution(format!("Failed to replace the bootstrap function: {}", e)))?;
+    Ok(())
+}
+
+/// Write the snapshot and then its manifest, so a manifest never describes a partial snapshot
+fn write(snapshot_path: &Path, snapshot: &[u8], manifest_path: &Path, manifest: &SnapshotManifest) -> Result<()> {
+    if let Some(dir) = snapshot_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Remove the old manifest first so the old one never vouches for the new snapshot
+    let _ = fs::remove_file(manifest_path);
+
+    let tmp_path = snapshot_path.with_extension("bin.tmp");
+    fs::write(&tmp_path, snapshot)?;
+    fs::rename(&tmp_path, snapshot_path)?;
+
+    fs::write(manifest_path, serde_json::to_vec_pretty(manifest)?)?;
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> Option<String> {
+    let contents = fs::read(path).ok()?;
+    Some(format!("{:x}", Sha256::digest(&contents)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_level_code_cannot_read_the_environment() {
+        let dir = std::env::temp_dir().join(format!("webserver-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = dir.join("main.js");
+        fs::write(&main, r#"export const key = Deno.env.get("WEBSERVER_SNAPSHOT_TEST_KEY");"#).unwrap();
+        std::env::set_var("WEBSERVER_SNAPSHOT_TEST_KEY", "sk-test");
+
+        let mut source = SnapshotSource {
+            main_module: ModuleSpecifier::from_file_path(&main).unwrap(),
+            cache_dir: dir.join("cache"),
+            vendor_dir: None,
+            // Workers may read the variable, the snapshot build may not
+            permissions: DenoPermissions {
+                allow_env: Some(vec!["WEBSERVER_SNAPSHOT_TEST_KEY".to_string()]),
+                ..Default::default()
+            },
+            unstable: false,
+            services: HostServices::default(),
+            ops: Vec::new(),
+        };
+        source.ops = op_names(&mut runtime(&source, &ModuleGraph::default()).unwrap()).unwrap();
+
+        let result = build(&source);
+        std::env::remove_var("WEBSERVER_SNAPSHOT_TEST_KEY");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let err = result.err().expect("reading the environment fails");
+        assert!(err.to_string().contains("env access"), "{}", err);
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, ServerError};
+
+/// File name prefix of the SQLite database behind `Deno.openKv()`, including its WAL files
+const KV_FILE_PREFIX: &str = "kv.sqlite3";
+
+/// Which application a storage directory belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageScope {
+    /// The application at `DENO_APP_PATH`
+    Main,
+    /// A module mounted from the route table, by mount name
+    Mount(String),
+    /// A function bundle deployed through the admin API, by bundle name
+    Bundle(String),
+}
+
+impl StorageScope {
+    /// Parse the form used in admin API paths: `main`, `mounts/<name>` or `bundles/<name>`
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope.split_once('/') {
+            None if scope == "main" => Some(Self::Main),
+            Some(("mounts", name)) if is_valid_name(name) => Some(Self::Mount(name.to_string())),
+            Some(("bundles", name)) if is_valid_name(name) => Some(Self::Bundle(name.to_string())),
+            _ => None,
+        }
+    }
+
+    fn relative_dir(&self) -> PathBuf {
+        match self {
+            Self::Main => PathBuf::from("main"),
+            Self::Mount(name) => Path::new("mounts").join(name),
+            Self::Bundle(name) => Path::new("bundles").join(name),
+        }
+    }
+}
+
+impl fmt::Display for StorageScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => f.write_str("main"),
+            Self::Mount(name) => write!(f, "mounts/{}", name),
+            Self::Bundle(name) => write!(f, "bundles/{}", name),
+        }
+    }
+}
+
+/// Kind of state kept in an application's storage directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+    /// Responses stored through the Cache API
+    Cache,
+    /// `localStorage`
+    LocalStorage,
+    /// The default `Deno.openKv()` database
+    Kv,
+}
+
+/// Bytes used by each kind of storage
+#[derive(Debug, Default, Serialize)]
+pub struct StorageUsage {
+    pub cache: u64,
+    pub local_storage: u64,
+    pub kv: u64,
+}
+
+/// Persistent storage of one application under `<data dir>/storage/`
+///
+/// The `cache` directory backs the Cache API, and the `origin` directory holds
+/// `localStorage` and the SQLite database opened by `Deno.openKv()` without a path.
+#[derive(Debug, Clone)]
+pub struct AppStorage {
+    scope: StorageScope,
+    root: PathBuf,
+}
+
+impl AppStorage {
+    /// Storage of `scope` inside `data_dir`
+    pub fn new(data_dir: &Path, scope: StorageScope) -> Self {
+        let root = data_dir.join("storage").join(scope.relative_dir());
+        Self { scope, root }
+    }
+
+    /// Every application that has storage in `data_dir`
+    pub fn list(data_dir: &Path) -> Result<Vec<Self>> {
+        let storage_dir = data_dir.join("storage");
+        let mut apps = Vec::new();
+
+        if storage_dir.join("main").is_dir() {
+            apps.push(Self::new(data_dir, StorageScope::Main));
+        }
+        for name in app_names(&storage_dir.join("mounts"))? {
+            apps.push(Self::new(data_dir, StorageScope::Mount(name)));
+        }
+        for name in app_names(&storage_dir.join("bundles"))? {
+            apps.push(Self::new(data_dir, StorageScope::Bundle(name)));
+        }
+
+        Ok(apps)
+    }
+
+    /// Application the storage belongs to
+    pub fn scope(&self) -> &StorageScope {
+        &self.scope
+    }
+
+    /// Directory backing the Cache API
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join("cache")
+    }
+
+    /// Directory backing `localStorage` and `Deno.openKv()`
+    pub fn origin_dir(&self) -> PathBuf {
+        self.root.join("origin")
+    }
+
+    /// Whether the application has stored anything yet
+    pub fn exists(&self) -> bool {
+        self.root.is_dir()
+    }
+
+    /// Create the storage directories if they do not exist yet
+    pub fn create(&self) -> Result<()> {
+        for dir in [self.cache_dir(), self.origin_dir()] {
+            fs::create_dir_all(&dir)
+                .map_err(|e| ServerError::Config(format!("Failed to create storage directory {:?}: {}", dir, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Bytes used by each kind of storage
+    pub fn usage(&self) -> Result<StorageUsage> {
+        let mut usage = StorageUsage {
+            cache: dir_size(&self.cache_dir())?,
+            ..Default::default()
+        };
+        for (path, is_kv) in self.origin_entries()? {
+            let size = dir_size(&path)?;
+            if is_kv {
+                usage.kv += size;
+            } else {
+                usage.local_storage += size;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Delete one kind of storage, or all of it
+    ///
+    /// Isolates that have the files open keep writing to the deleted copies, so
+    /// reload them afterwards.
+    pub fn clear(&self, kind: Option<StorageKind>) -> Result<()> {
+        if matches!(kind, None | Some(StorageKind::Cache)) {
+            remove(&self.cache_dir())?;
+        }
+        for (path, is_kv) in self.origin_entries()? {
+            let selected = match kind {
+                None => true,
+                Some(StorageKind::Kv) => is_kv,
+                Some(StorageKind::LocalStorage) => !is_kv,
+                Some(StorageKind::Cache) => false,
+            };
+            if selected {
+                remove(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete the application's storage directory entirely
+    pub fn remove(&self) -> Result<()> {
+        remove(&self.root)
+    }
+
+    /// Entries of the origin directory, flagged when they belong to the KV database
+    fn origin_entries(&self) -> Result<Vec<(PathBuf, bool)>> {
+        let entries = match fs::read_dir(self.origin_dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let is_kv = entry.file_name().to_string_lossy().starts_with(KV_FILE_PREFIX);
+            paths.push((entry.path(), is_kv));
+        }
+        Ok(paths)
+    }
+}
+
+/// Mount and bundle names double as directory names
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Sorted names of the application directories in `dir`
+fn app_names(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && is_valid_name(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn dir_size(path: &Path) -> Result<u64> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += dir_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+fn remove(path: &Path) -> Result<()> {
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match result {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix_web::dev::Server as ActixServer;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use notify::{RecursiveMode, Watcher};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{CertificateError, DigitallySignedStruct, DistinguishedName, RootCertStore, SignatureScheme};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::client_identity::ClientIdentity;
+use crate::config::ClientAuthConfig;
+use crate::error::{Result, ServerError};
+
+/// Quiet period after a change before reloading, so a renewal that writes the
+/// certificate and the key one after the other is loaded once, with both files
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Hands every TLS handshake the current certificate, which is replaced when its files change
+#[derive(Debug)]
+pub struct CertResolver {
+    cert_file: PathBuf,
+    key_file: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    /// Load the PEM certificate chain and private key
+    pub fn load(cert_file: &Path, key_file: &Path) -> Result<Self> {
+        let current = load_certified_key(cert_file, key_file)?;
+        Ok(Self {
+            cert_file: cert_file.to_path_buf(),
+            key_file: key_file.to_path_buf(),
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    /// Load the files again; invalid files leave the current certificate in place
+    pub fn reload(&self) -> Result<()> {
+        let certified = load_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().unwrap() = Arc::new(certified);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// Read a certificate chain and the private key it was issued for
+fn load_certified_key(cert_file: &Path, key_file: &Path) -> Result<CertifiedKey> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| ServerError::Tls(format!("Failed to read {:?}: {}", path, e)))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_file)?)
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| ServerError::Tls(format!("Invalid certificate {:?}: {}", cert_file, e)))?;
+    if certs.is_empty() {
+        return Err(ServerError::Tls(format!("No certificate found in {:?}", cert_file)));
+    }
+
+    let key = rustls_pemfile::private_key(&mut open(key_file)?)
+        .map_err(|e| ServerError::Tls(format!("Invalid private key {:?}: {}", key_file, e)))?
+        .ok_or_else(|| ServerError::Tls(format!("No private key found in {:?}", key_file)))?;
+    let key = ring::sign::any_supported_type(&key)
+        .map_err(|e| ServerError::Tls(format!("Unsupported private key {:?}: {}", key_file, e)))?;
+
+    let certified = CertifiedKey::new(certs, key);
+    certified
+        .keys_match()
+        .map_err(|e| ServerError::Tls(format!("{:?} does not match {:?}: {}", key_file, cert_file, e)))?;
+    Ok(certified)
+}
+
+/// rustls configuration serving the certificates of `resolver`, verifying client
+/// certificates when `client_auth` is given
+pub fn server_config(resolver: Arc<CertResolver>, client_auth: Option<&ClientAuthConfig>) -> Result<rustls::ServerConfig> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ServerError::Tls(e.to_string()))?;
+
+    let config = match client_auth {
+        Some(client_auth) => builder
+            .with_client_cert_verifier(AllowedClients::new(client_auth, provider)?)
+            .with_cert_resolver(resolver),
+        None => builder.with_no_client_auth().with_cert_resolver(resolver),
+    };
+    Ok(config)
+}
+
+/// Verifies client certificates against the CA bundle, then against the allowed subject and SAN patterns
+///
+/// A certificate that chains to the CA but matches no pattern fails the handshake, so
+/// handlers only ever see identities that passed both checks.
+#[derive(Debug)]
+struct AllowedClients {
+    verifier: Arc<dyn ClientCertVerifier>,
+    subjects: Vec<String>,
+    sans: Vec<String>,
+}
+
+impl AllowedClients {
+    fn new(config: &ClientAuthConfig, provider: Arc<rustls::crypto::CryptoProvider>) -> Result<Arc<Self>> {
+        let file = File::open(&config.ca_file)
+            .map_err(|e| ServerError::Tls(format!("Failed to read {:?}: {}", config.ca_file, e)))?;
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut BufReader::new(file)) {
+            let cert = cert.map_err(|e| ServerError::Tls(format!("Invalid client CA bundle {:?}: {}", config.ca_file, e)))?;
+            roots
+                .add(cert)
+                .map_err(|e| ServerError::Tls(format!("Invalid client CA in {:?}: {}", config.ca_file, e)))?;
+        }
+        if roots.is_empty() {
+            return Err(ServerError::Tls(format!("No CA certificate found in {:?}", config.ca_file)));
+        }
+
+        let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+        let builder = if config.required { builder } else { builder.allow_unauthenticated() };
+        let verifier = builder
+            .build()
+            .map_err(|e| ServerError::Tls(format!("Invalid client CA bundle {:?}: {}", config.ca_file, e)))?;
+
+        Ok(Arc::new(Self {
+            verifier,
+            subjects: config.allowed_subjects.clone(),
+            sans: config.allowed_sans.clone(),
+        }))
+    }
+}
+
+impl ClientCertVerifier for AllowedClients {
+    fn offer_client_auth(&self) -> bool {
+        self.verifier.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.verifier.client_auth_mandatory()
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.verifier.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        let verified = self.verifier.verify_client_cert(end_entity, intermediates, now)?;
+
+        let identity = ClientIdentity::from_der(end_entity).map_err(rustls::Error::General)?;
+        if !identity.is_allowed(&self.subjects, &self.sans) {
+            warn!("Rejected client certificate for {:?} with SANs {:?}", identity.subject, identity.sans);
+            return Err(rustls::Error::InvalidCertificate(CertificateError::ApplicationVerificationFailure));
+        }
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+/// Reload the certificate whenever its certificate or key file changes
+///
+/// The directories holding the files are watched, so files replaced by a rename are
+/// picked up as well as files rewritten in place.
+pub fn watch(resolver: Arc<CertResolver>) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() => {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => warn!("Certificate watcher error: {}", err),
+    })
+    .map_err(|e| ServerError::Server(format!("Failed to start certificate watcher: {}", e)))?;
+
+    let files = [resolver.cert_file.clone(), resolver.key_file.clone()];
+    let mut dirs: Vec<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+    dirs.dedup();
+    for dir in dirs {
+        // A bare file name lives in the current directory
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| ServerError::Server(format!("Failed to watch {:?}: {}", dir, e)))?;
+        debug!("Watching {:?} for certificate changes", dir);
+    }
+
+    actix_web::rt::spawn(async move {
+        // Keep the watcher alive for as long as the server runs
+        let _watcher = watcher;
+
+        while let Some(path) = rx.recv().await {
+            if !is_certificate_change(&path, &files) {
+                continue;
+            }
+
+            // Collect every change of the renewal before loading it
+            let quiet = tokio::time::sleep(DEBOUNCE);
+            tokio::pin!(quiet);
+            loop {
+                tokio::select! {
+                    _ = &mut quiet => break,
+                    Some(_) = rx.recv() => {}
+                }
+            }
+
+            match resolver.reload() {
+                Ok(()) => info!("Reloaded TLS certificate from {:?}", resolver.cert_file),
+                Err(err) => error!("Keeping the current TLS certificate: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Whether a change to `path` may have replaced one of `files`
+///
+/// Kubernetes secret volumes swap a `..data` symlink instead of touching the files
+/// themselves, so changes to entries starting with `..` count as well.
+fn is_certificate_change(path: &Path, files: &[PathBuf]) -> bool {
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    name.to_string_lossy().starts_with("..") || files.iter().any(|file| file.file_name() == Some(name))
+}
+
+/// Value of the Strict-Transport-Security header for `max_age`
+pub fn hsts_header(max_age: Duration) -> HeaderValue {
+    HeaderValue::from_str(&format!("max-age={}", max_age.as_secs())).expect("max-age is a valid header value")
+}
+
+/// Listen for plain HTTP on `addr`, redirecting every request to HTTPS on `https_port`
+pub fn redirect_server(addr: SocketAddr, https_port: u16) -> Result<ActixServer> {
+    let server = HttpServer::new(move || {
+        App::new().default_service(web::to(move |req: HttpRequest| redirect_to_https(req, https_port)))
+    })
+    .workers(1)
+    .disable_signals()
+    .bind(addr)?
+    .run();
+    Ok(server)
+}
+
+/// Handler answering with a permanent redirect to the same URL over HTTPS
+async fn redirect_to_https(req: HttpRequest, https_port: u16) -> HttpResponse {
+    let host = host_name(req.connection_info().host()).to_string();
+    let authority = if https_port == 443 { host } else { format!("{}:{}", host, https_port) };
+    let path = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/");
+
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, format!("https://{}{}", authority, path)))
+        .finish()
+}
+
+/// `host` without its port, keeping the brackets of an IPv6 address
+fn host_name(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.split_inclusive(']').next().unwrap_or(host);
+    }
+    host.split(':').next().unwrap_or(host)
+}
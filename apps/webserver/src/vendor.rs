@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use deno_core::error::{generic_error, AnyError};
+use deno_core::ModuleSpecifier;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::error::{Result, ServerError};
+
+/// Import map written by `deno vendor`
+#[derive(Debug, Default, Deserialize)]
+struct ImportMap {
+    #[serde(default)]
+    imports: HashMap<String, String>,
+}
+
+/// Subset of `deno.lock` used to check vendored remote modules
+#[derive(Debug, Default, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    remote: HashMap<String, String>,
+}
+
+/// Local copy of every remote, `npm:` and `jsr:` dependency of the Deno application
+///
+/// The directory is filled ahead of time so the server never touches the network
+/// to load code:
+///
+/// - `import_map.json` maps `https:` and `jsr:` specifiers (or URL prefixes ending
+///   in `/`) to files in the directory, as produced by `deno vendor`
+/// - `node_modules/` holds npm packages, as produced by `npm install`
+/// - `deno.lock`, if present, pins the SHA-256 of each remote module
+#[derive(Clone)]
+pub struct Vendor {
+    dir: PathBuf,
+    exact: HashMap<String, ModuleSpecifier>,
+    prefixes: Vec<(String, ModuleSpecifier)>,
+    lock: HashMap<String, String>,
+}
+
+impl Vendor {
+    /// Load the vendor directory's import map and lockfile
+    pub fn load(dir: &Path) -> Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .map_err(|e| ServerError::Config(format!("Vendor directory {:?} is not accessible: {}", dir, e)))?;
+        let base = ModuleSpecifier::from_directory_path(&dir)
+            .map_err(|_| ServerError::Config(format!("Invalid vendor directory: {:?}", dir)))?;
+
+        let import_map: ImportMap = read_json(&dir.join("import_map.json"))?.unwrap_or_default();
+        let lock: Lockfile = read_json(&dir.join("deno.lock"))?.unwrap_or_default();
+
+        let mut exact = HashMap::new();
+        let mut prefixes = Vec::new();
+        for (key, target) in import_map.imports {
+            let target = base
+                .join(&target)
+                .map_err(|e| ServerError::Config(format!("Invalid import map entry {:?}: {}", key, e)))?;
+            if key.ends_with('/') {
+                prefixes.push((key, target));
+            } else {
+                exact.insert(key, target);
+            }
+        }
+
+        // Longest prefix wins, as in import maps
+        prefixes.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+
+        debug!(
+            "Loaded vendor directory {:?} with {} import(s) and {} prefix(es)",
+            dir,
+            exact.len(),
+            prefixes.len()
+        );
+
+        Ok(Self {
+            dir,
+            exact,
+            prefixes,
+            lock: lock.remote,
+        })
+    }
+
+    /// The vendor directory, canonicalized
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Map a remote, `npm:` or `jsr:` specifier onto a vendored file
+    pub fn resolve(&self, specifier: &ModuleSpecifier) -> std::result::Result<ModuleSpecifier, AnyError> {
+        if specifier.scheme() == "npm" {
+            return self.resolve_npm(specifier.path());
+        }
+
+        let resolved = self.lookup(specifier.as_str()).ok_or_else(|| {
+            generic_error(format!(
+                "Module {} is not in the vendor directory {}; vendor it with `deno vendor` before starting the server",
+                specifier,
+                self.dir.display()
+            ))
+        })?;
+
+        self.verify(specifier, &resolved)?;
+        Ok(resolved)
+    }
+
+    /// Resolve a bare specifier imported from inside a vendored npm package
+    pub fn resolve_bare(
+        &self,
+        specifier: &str,
+        referrer: &ModuleSpecifier,
+    ) -> Option<std::result::Result<ModuleSpecifier, AnyError>> {
+        let referrer_path = referrer.to_file_path().ok()?;
+        if !referrer_path.starts_with(self.dir.join("node_modules")) {
+            return None;
+        }
+
+        Some(self.resolve_npm(specifier))
+    }
+
+    fn lookup(&self, specifier: &str) -> Option<ModuleSpecifier> {
+        if let Some(target) = self.exact.get(specifier) {
+            return Some(target.clone());
+        }
+
+        self.prefixes.iter().find_map(|(prefix, target)| {
+            let rest = specifier.strip_prefix(prefix.as_str())?;
+            target.join(rest).ok()
+        })
+    }
+
+    /// Check a vendored remote module against the lockfile, if it is pinned there
+    fn verify(&self, specifier: &ModuleSpecifier, resolved: &ModuleSpecifier) -> std::result::Result<(), AnyError> {
+        let Some(expected) = self.lock.get(specifier.as_str()) else {
+            return Ok(());
+        };
+
+        let path = resolved
+            .to_file_path()
+            .map_err(|_| generic_error(format!("Vendored module {} is not a file", resolved)))?;
+        let contents = fs::read(&path)
+            .map_err(|e| generic_error(format!("Failed to read vendored module {}: {}", path.display(), e)))?;
+        let actual = format!("{:x}", Sha256::digest(&contents));
+
+        if &actual != expected {
+            return Err(generic_error(format!(
+                "Vendored module {} does not match deno.lock (expected {}, found {})",
+                specifier, expected, actual
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Resolve `name[@version][/subpath]` against `node_modules`
+    fn resolve_npm(&self, specifier: &str) -> std::result::Result<ModuleSpecifier, AnyError> {
+        let (name, version, subpath) = parse_npm_specifier(specifier)
+            .ok_or_else(|| generic_error(format!("Invalid npm specifier: npm:{}", specifier)))?;
+
+        let package_dir = self.dir.join("node_modules").join(name);
+        let package_json = package_dir.join("package.json");
+        let manifest: serde_json::Value = read_json(&package_json)
+            .map_err(|e| generic_error(e.to_string()))?
+            .ok_or_else(|| {
+                generic_error(format!(
+                    "npm package {} is not in the vendor directory {}; install it with `npm install --prefix {} {}`",
+                    name,
+                    self.dir.display(),
+                    self.dir.display(),
+                    name
+                ))
+            })?;
+
+        // Exact versions must match what was vendored
+        if let (Some(wanted), Some(found)) = (version, manifest["version"].as_str()) {
+            if wanted.chars().all(|c| c.is_ascii_digit() || c == '.') && wanted != found {
+                return Err(generic_error(format!(
+                    "npm package {}@{} is required but {}@{} is vendored",
+                    name, wanted, name, found
+                )));
+            }
+        }
+
+        let entry = package_entry(&manifest, subpath)
+            .ok_or_else(|| generic_error(format!("npm package {} has no ESM entry point for {:?}", name, subpath)))?;
+        let path = package_dir.join(entry.trim_start_matches("./"));
+
+        ModuleSpecifier::from_file_path(&path)
+            .map_err(|_| generic_error(format!("Invalid path for npm package {}: {}", name, path.display())))
+    }
+}
+
+/// Split `name[@version][/subpath]`, handling `@scope/name`
+fn parse_npm_specifier(specifier: &str) -> Option<(&str, Option<&str>, Option<&str>)> {
+    let specifier = specifier.trim_start_matches('/');
+    let name_end = if specifier.starts_with('@') {
+        let scope_end = specifier.find('/')?;
+        specifier[scope_end + 1..].find('/').map(|i| scope_end + 1 + i)
+    } else {
+        specifier.find('/')
+    };
+
+    let (name_and_version, subpath) = match name_end {
+        Some(end) => (&specifier[..end], Some(&specifier[end + 1..])),
+        None => (specifier, None),
+    };
+
+    // The version separator is the last `@` that is not the scope marker
+    let (name, version) = match name_and_version.rfind('@') {
+        Some(at) if at > 0 => (&name_and_version[..at], Some(&name_and_version[at + 1..])),
+        _ => (name_and_version, None),
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, version, subpath.filter(|s| !s.is_empty())))
+}
+
+/// Find the file a package exposes for a subpath, preferring ESM conditions
+fn package_entry(manifest: &serde_json::Value, subpath: Option<&str>) -> Option<String> {
+    let export_key = subpath.map_or_else(|| ".".to_string(), |s| format!("./{}", s));
+
+    match &manifest["exports"] {
+        serde_json::Value::Null => {}
+        serde_json::Value::String(entry) if subpath.is_none() => return Some(entry.clone()),
+        serde_json::Value::Object(map) if map.keys().all(|k| k.starts_with('.')) => {
+            return map.get(&export_key).and_then(select_condition);
+        }
+        conditions if subpath.is_none() => return select_condition(conditions),
+        _ => {}
+    }
+
+    match subpath {
+        Some(subpath) => Some(subpath.to_string()),
+        None => manifest["module"]
+            .as_str()
+            .or_else(|| manifest["main"].as_str())
+            .map(str::to_string)
+            .or_else(|| Some("index.js".to_string())),
+    }
+}
+
+/// Pick the target of a conditional export
+fn select_condition(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(entry) => Some(entry.clone()),
+        serde_json::Value::Object(conditions) => ["deno", "import", "module", "default"]
+            .iter()
+            .find_map(|condition| conditions.get(*condition).and_then(select_condition)),
+        _ => None,
+    }
+}
+
+/// Read a JSON file, returning `None` if it does not exist
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| ServerError::Config(format!("Invalid JSON in {:?}: {}", path, e))),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(ServerError::Io(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vendor directory with `files` written into it, only this test uses
+    fn vendor_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("webserver-vendor-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn specifier(url: &str) -> ModuleSpecifier {
+        ModuleSpecifier::parse(url).unwrap()
+    }
+
+    fn sha256(contents: &str) -> String {
+        format!("{:x}", Sha256::digest(contents.as_bytes()))
+    }
+
+    #[test]
+    fn npm_specifiers_split_into_name_version_and_subpath() {
+        let cases = [
+            ("lodash", Some(("lodash", None, None))),
+            ("lodash@4.17.21", Some(("lodash", Some("4.17.21"), None))),
+            ("lodash@^4/fp", Some(("lodash", Some("^4"), Some("fp")))),
+            ("lodash/fp/map.js", Some(("lodash", None, Some("fp/map.js")))),
+            ("/lodash@4", Some(("lodash", Some("4"), None))),
+            ("lodash/", Some(("lodash", None, None))),
+            ("@std/path", Some(("@std/path", None, None))),
+            ("@std/path@1.0.0", Some(("@std/path", Some("1.0.0"), None))),
+            ("@std/path@1.0.0/posix", Some(("@std/path", Some("1.0.0"), Some("posix")))),
+            ("@std", None),
+            ("", None),
+            ("@4", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_npm_specifier(input), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn package_entries_prefer_esm_conditions() {
+        let cases = [
+            (serde_json::json!({}), None, Some("index.js")),
+            (serde_json::json!({ "main": "lib/index.cjs" }), None, Some("lib/index.cjs")),
+            (serde_json::json!({ "main": "lib/index.cjs", "module": "esm/index.js" }), None, Some("esm/index.js")),
+            (serde_json::json!({ "main": "lib/index.cjs" }), Some("fp.js"), Some("fp.js")),
+            (serde_json::json!({ "exports": "./esm/index.js" }), None, Some("./esm/index.js")),
+            (serde_json::json!({ "exports": { "require": "./index.cjs", "import": "./index.mjs" } }), None, Some("./index.mjs")),
+            (serde_json::json!({ "exports": { "node": "./node.js", "default": "./index.js" } }), None, Some("./index.js")),
+            (serde_json::json!({ "exports": { "deno": "./deno.js", "import": "./index.mjs" } }), None, Some("./deno.js")),
+            (
+                serde_json::json!({ "exports": { ".": { "import": "./index.mjs" }, "./fp": { "import": { "default": "./fp.mjs" } } } }),
+                Some("fp"),
+                Some("./fp.mjs"),
+            ),
+            (serde_json::json!({ "exports": { ".": "./index.js" } }), Some("internal"), None),
+            (serde_json::json!({ "exports": { ".": { "require": "./index.cjs" } } }), None, None),
+        ];
+        for (manifest, subpath, expected) in cases {
+            assert_eq!(package_entry(&manifest, subpath).as_deref(), expected, "{} {:?}", manifest, subpath);
+        }
+    }
+
+    #[test]
+    fn import_map_entries_resolve_to_vendored_files() {
+        let dir = vendor_dir(
+            "import-map",
+            &[
+                (
+                    "import_map.json",
+                    r#"{ "imports": {
+                        "https://deno.land/std@0.200.0/": "./deno.land/std@0.200.0/",
+                        "https://deno.land/std@0.200.0/path/": "./std-path/",
+                        "https://esm.sh/preact": "./esm.sh/preact.js",
+                        "jsr:@std/assert": "./jsr/assert/mod.ts"
+                    } }"#,
+                ),
+                ("deno.land/std@0.200.0/fs/mod.ts", ""),
+                ("std-path/mod.ts", ""),
+                ("esm.sh/preact.js", ""),
+                ("jsr/assert/mod.ts", ""),
+            ],
+        );
+        let vendor = Vendor::load(&dir).unwrap();
+        let root = dir.canonicalize().unwrap();
+
+        let cases = [
+            ("https://esm.sh/preact", Some("esm.sh/preact.js")),
+            ("https://deno.land/std@0.200.0/fs/mod.ts", Some("deno.land/std@0.200.0/fs/mod.ts")),
+            ("https://deno.land/std@0.200.0/path/mod.ts", Some("std-path/mod.ts")),
+            ("jsr:@std/assert", Some("jsr/assert/mod.ts")),
+            ("https://esm.sh/react", None),
+            ("https://deno.land/std@0.201.0/fs/mod.ts", None),
+        ];
+        for (url, expected) in cases {
+            let resolved = vendor.resolve(&specifier(url)).ok();
+            let expected = expected.map(|path| ModuleSpecifier::from_file_path(root.join(path)).unwrap());
+            assert_eq!(resolved, expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn vendored_modules_must_match_the_lockfile() {
+        let pinned = "export const pinned = true;";
+        let lock = serde_json::json!({ "remote": {
+            "https://example.com/pinned.ts": sha256(pinned),
+            "https://example.com/tampered.ts": sha256(pinned),
+        } })
+        .to_string();
+        let dir = vendor_dir(
+            "lockfile",
+            &[
+                ("import_map.json", r#"{ "imports": { "https://example.com/": "./example.com/" } }"#),
+                ("deno.lock", lock.as_str()),
+                ("example.com/pinned.ts", pinned),
+                ("example.com/tampered.ts", "export const pinned = false;"),
+                ("example.com/unpinned.ts", "export const unpinned = true;"),
+            ],
+        );
+        let vendor = Vendor::load(&dir).unwrap();
+
+        assert!(vendor.resolve(&specifier("https://example.com/pinned.ts")).is_ok());
+        assert!(vendor.resolve(&specifier("https://example.com/unpinned.ts")).is_ok());
+
+        let err = vendor.resolve(&specifier("https://example.com/tampered.ts")).unwrap_err();
+        assert!(err.to_string().contains("does not match deno.lock"), "{}", err);
+    }
+
+    #[test]
+    fn npm_packages_resolve_from_node_modules() {
+        let dir = vendor_dir(
+            "npm",
+            &[
+                (
+                    "node_modules/@scope/tools/package.json",
+                    r#"{ "version": "1.2.3", "exports": { ".": { "import": "./esm/index.js" } } }"#,
+                ),
+                ("node_modules/@scope/tools/esm/index.js", ""),
+            ],
+        );
+        let vendor = Vendor::load(&dir).unwrap();
+        let entry = dir.canonicalize().unwrap().join("node_modules/@scope/tools/esm/index.js");
+        let entry = ModuleSpecifier::from_file_path(entry).unwrap();
+
+        for url in ["npm:@scope/tools", "npm:@scope/tools@1.2.3", "npm:@scope/tools@^1"] {
+            assert_eq!(vendor.resolve(&specifier(url)).unwrap(), entry, "{}", url);
+        }
+        for url in ["npm:@scope/tools@1.2.4", "npm:@scope/other", "npm:@scope/tools/missing"] {
+            assert!(vendor.resolve(&specifier(url)).is_err(), "{}", url);
+        }
+    }
+}
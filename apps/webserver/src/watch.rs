@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Weak;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::error::{Result, ServerError};
+use crate::loader::ModuleGraph;
+use crate::pool::DenoPool;
+
+/// Quiet period after a change before reloading, so one save triggers one reload
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How often an idle watcher checks whether its pool is still in use
+const POOL_CHECK: Duration = Duration::from_secs(30);
+
+/// Watch the Deno application's import graph and reload the pool when a module changes
+///
+/// The directories of every loaded module are watched, and the set is refreshed after
+/// each reload so newly imported files are picked up. The watcher only holds a weak
+/// reference, and stops once the pool has been dropped, such as after a restart of its mount.
+pub fn spawn(pool: Weak<DenoPool>, graph: ModuleGraph) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+        Ok(event) if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() => {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => warn!("File watcher error: {}", err),
+    })
+    .map_err(|e| ServerError::Server(format!("Failed to start file watcher: {}", e)))?;
+
+    let mut watched = HashSet::new();
+    watch_graph(&mut watcher, &graph, &mut watched);
+    info!("Watching {} Deno module(s) for changes", graph.files().len());
+
+    actix_web::rt::spawn(async move {
+        let mut pool_check = tokio::time::interval(POOL_CHECK);
+        loop {
+            let path = tokio::select! {
+                path = rx.recv() => path,
+                _ = pool_check.tick() => {
+                    if pool.strong_count() == 0 {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let Some(path) = path else {
+                break;
+            };
+
+            // Collect the burst of events an editor produces for a single save
+            let mut changed = vec![path];
+            let quiet = tokio::time::sleep(DEBOUNCE);
+            tokio::pin!(quiet);
+            loop {
+                tokio::select! {
+                    _ = &mut quiet => break,
+                    Some(path) = rx.recv() => changed.push(path),
+                }
+            }
+
+            let changed: Vec<PathBuf> = changed
+                .into_iter()
+                .filter(|path| graph.contains(&path.canonicalize().unwrap_or_else(|_| path.clone())))
+                .collect();
+            if changed.is_empty() {
+                continue;
+            }
+
+            let Some(pool) = pool.upgrade() else {
+                break;
+            };
+            info!("Reloading Deno application after changes to {:?}", changed);
+            match pool.reload().await {
+                Ok(()) => info!("Deno application reloaded"),
+                Err(err) => error!("Deno application reload failed: {}", err),
+            }
+
+            watch_graph(&mut watcher, &graph, &mut watched);
+        }
+        debug!("Stopped watching Deno modules; their pool was dropped");
+    });
+
+    Ok(())
+}
+
+/// Watch the directory of every module in the graph that is not watched yet
+fn watch_graph(watcher: &mut RecommendedWatcher, graph: &ModuleGraph, watched: &mut HashSet<PathBuf>) {
+    for file in graph.files() {
+        let Some(dir) = file.parent().map(Path::to_path_buf) else {
+            continue;
+        };
+        if watched.contains(&dir) {
+            continue;
+        }
+
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                debug!("Watching {:?}", dir);
+                watched.insert(dir);
+            }
+            Err(err) => warn!("Failed to watch {:?}: {}", dir, err),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// Type declarations for the `Webserver` global available to the Deno application
+// when it runs inside the webserver. Reference this file from the application:
+//
+//     /// <reference path="../webserver/types/webserver.d.ts" />
+
+/** JSON-compatible values that can be stored and logged */
+type WebserverJson =
+  | null
+  | boolean
+  | number
+  | string
+  | WebserverJson[]
+  | { [key: string]: WebserverJson };
+
+interface WebserverLogger {
+  /**
+   * Write a message to the server log. `fields` are recorded as structured
+   * JSON next to the request ID and function name of the current call.
+   */
+  (message: string, fields?: Record<string, WebserverJson>): void;
+}
+
+interface WebserverKv {
+  /** Value stored under `key`, or `undefined` */
+  get<T extends WebserverJson = WebserverJson>(key: string): T | undefined;
+  /** Store a JSON-compatible value, replacing any previous one */
+  set(key: string, value: WebserverJson): void;
+  /** Remove `key`, returning whether it existed */
+  delete(key: string): boolean;
+  /** Keys starting with `prefix`, sorted */
+  list(prefix?: string): string[];
+}
+
+interface WebserverSecrets {
+  /** Secret configured on the server, or `undefined` if there is none by that name */
+  get(name: string): string | undefined;
+}
+
+interface WebserverMetrics {
+  /**
+   * Add `by` (default 1) to a counter reported by `/api/status`. Names may
+   * contain letters, digits, `_`, `.` and `:`.
+   */
+  increment(name: string, by?: number): void;
+}
+
+/** Identity from a client certificate the server verified (mutual TLS) */
+interface WebserverClientIdentity {
+  /** Distinguished name of the subject, such as `CN=billing, O=Example` */
+  subject: string;
+  /** Common name of the subject */
+  commonName: string | null;
+  /** DNS names, URIs, email and IP addresses from the subject alternative names */
+  sans: string[];
+  /** Serial number as colon-separated hex bytes */
+  serial: string;
+}
+
+interface WebserverCallContext {
+  /** ID of the HTTP request being served, echoed in its `X-Request-Id` header */
+  requestId: string | null;
+  /** Name of the function being called */
+  function: string | null;
+  /** Caller authenticated by a client certificate, or `null` */
+  client: WebserverClientIdentity | null;
+}
+
+declare namespace Webserver {
+  const log: {
+    readonly trace: WebserverLogger;
+    readonly debug: WebserverLogger;
+    readonly info: WebserverLogger;
+    readonly warn: WebserverLogger;
+    readonly error: WebserverLogger;
+  };
+  /** Key/value store shared by all isolates; contents are lost on restart */
+  const kv: WebserverKv;
+  const secrets: WebserverSecrets;
+  const metrics: WebserverMetrics;
+  /** Context of the call being served; every field is `null` outside of a call */
+  const context: WebserverCallContext;
+}
+
+/**
+ * JSON Schemas of a function callable through `POST /api/functions/{name}`.
+ * Export an object of these as `schemas`, keyed by function name.
+ */
+interface WebserverFunctionSchema {
+  /** Schema the request body must match; any input is accepted when omitted */
+  input?: Record<string, unknown>;
+  /** Schema the function's result must match */
+  output?: Record<string, unknown>;
+}